@@ -2,9 +2,13 @@
 
 use advent_of_code_2020::UnsolvedError;
 use itertools::Itertools;
-use std::collections::{HashMap, HashSet, VecDeque};
+use num::{BigUint, One, Zero};
+use std::collections::BTreeMap;
 use std::error::Error;
+use std::fmt;
+use std::fmt::Formatter;
 use std::ops::Deref;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Debug, PartialEq, Clone)]
 struct Adapter {
@@ -18,157 +22,395 @@ impl Adapter {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+/// How often each difference between neighbouring ratings occurs in the chain that uses
+/// every adapter, from the outlet to the device.
+#[derive(Debug, PartialEq, Clone, Default)]
 struct Distribution {
-    one: u32,
-    two: u32,
-    three: u32,
+    counts: BTreeMap<i32, u32>,
+    // (from, to) for every step larger than the maximum allowed
+    too_large: Vec<(i32, i32)>,
 }
 
 impl Distribution {
-    fn new() -> Distribution {
-        Distribution {
-            one: 0,
-            two: 0,
-            three: 0,
+    fn new(adapters: &[Adapter], outlet: i32, device: i32, max_gap: i32) -> Distribution {
+        let mut jolts: Vec<i32> = adapters.iter().map(|adapter| adapter.jolt).collect();
+        jolts.sort_unstable();
+
+        let mut distribution = Distribution::default();
+        let chain = std::iter::once(outlet)
+            .chain(jolts)
+            .chain(std::iter::once(device));
+        for (from, to) in chain.tuple_windows() {
+            let difference = to - from;
+            *distribution.counts.entry(difference).or_insert(0) += 1;
+            if difference > max_gap {
+                distribution.too_large.push((from, to));
+            }
         }
+        distribution
+    }
+
+    fn count(&self, difference: i32) -> u32 {
+        self.counts.get(&difference).copied().unwrap_or(0)
     }
 }
 
-fn part1_greedy(adapters: &Vec<Adapter>, final_jolt: i32) -> Result<Vec<Adapter>, UnsolvedError> {
-    let mut current_jolt = 0;
-    let mut solution: Vec<Adapter> = Vec::new();
-    let mut remaining_adapters = adapters.clone();
-    while !remaining_adapters.is_empty() {
-        let possible_adapters: Vec<&Adapter> = remaining_adapters
-            .iter()
-            .filter(|adapter| current_jolt >= adapter.jolt - 3 && current_jolt < adapter.jolt)
-            .collect();
+impl fmt::Display for Distribution {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (difference, count) in &self.counts {
+            writeln!(f, "{} jolt differences: {}", difference, count)?;
+        }
+        for (from, to) in &self.too_large {
+            writeln!(f, "{} to {} is too large a step", from, to)?;
+        }
+        Ok(())
+    }
+}
 
-        let smallest_adapter = possible_adapters
-            .into_iter()
-            .min_by_key(|a| a.jolt)
-            .ok_or(UnsolvedError)?
-            .clone();
+/// Where a chain through every adapter first gets stuck.
+#[derive(Debug, PartialEq, Clone)]
+struct Break {
+    jolt: i32,
+    // the lowest adapter not yet used, which is either too far away or rated the same
+    nearest: i32,
+}
 
-        current_jolt = smallest_adapter.jolt;
+/// Why the adapters can't all be chained from the outlet to the device.
+#[derive(Debug, PartialEq, Clone)]
+struct Diagnosis {
+    chain_break: Option<Break>,
+    // ratings shared by more than one adapter, which can never both be used
+    duplicates: Vec<i32>,
+    // ratings at or below the outlet, which nothing can plug into
+    unusable: Vec<i32>,
+    // the fewest ratings to add so that every gap is small enough
+    missing: Vec<i32>,
+}
 
-        remaining_adapters.remove(
-            remaining_adapters
-                .iter()
-                .position(|x| *x == smallest_adapter)
-                .unwrap(),
-        );
+impl Diagnosis {
+    fn is_feasible(&self) -> bool {
+        self.chain_break.is_none() && self.unusable.is_empty()
+    }
+}
 
-        solution.push(smallest_adapter);
+impl fmt::Display for Diagnosis {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.chain_break {
+            Some(Break { jolt, nearest }) => write!(
+                f,
+                "the chain breaks at {} jolts, the nearest remaining adapter is {}",
+                jolt, nearest
+            )?,
+            None if self.unusable.is_empty() => write!(f, "every adapter can be chained")?,
+            None => write!(f, "every other adapter can be chained")?,
+        }
+        if !self.unusable.is_empty() {
+            write!(
+                f,
+                "; remove the adapters at or below the outlet {}",
+                self.unusable.iter().join(", ")
+            )?;
+        }
+        if !self.duplicates.is_empty() {
+            write!(
+                f,
+                "; remove the duplicate adapters {}",
+                self.duplicates.iter().join(", ")
+            )?;
+        }
+        if !self.missing.is_empty() {
+            write!(f, "; add adapters {}", self.missing.iter().join(", "))?;
+        }
+        Ok(())
     }
+}
 
-    return Ok(solution);
+fn diagnose(adapters: &[Adapter], outlet: i32, device: i32, max_gap: i32) -> Diagnosis {
+    let mut jolts: Vec<i32> = adapters.iter().map(|adapter| adapter.jolt).collect();
+    jolts.sort_unstable();
+
+    let mut diagnosis = Diagnosis {
+        chain_break: None,
+        duplicates: Vec::new(),
+        unusable: Vec::new(),
+        missing: Vec::new(),
+    };
+    for pair in jolts.windows(2) {
+        if pair[0] == pair[1] && diagnosis.duplicates.last() != Some(&pair[0]) {
+            diagnosis.duplicates.push(pair[0]);
+        }
+    }
+
+    // adapters at or below the outlet are left out so every gap is measured from one in use
+    let usable = jolts
+        .iter()
+        .position(|&jolt| jolt > outlet)
+        .unwrap_or(jolts.len());
+    diagnosis.unusable = jolts[..usable].to_vec();
+    diagnosis.unusable.dedup();
+
+    // the device comes last, after every adapter
+    let mut current = outlet;
+    for &jolt in jolts[usable..].iter().chain(std::iter::once(&device)) {
+        let difference = jolt - current;
+        if diagnosis.chain_break.is_none() && (difference < 1 || difference > max_gap) {
+            diagnosis.chain_break = Some(Break {
+                jolt: current,
+                nearest: jolt,
+            });
+        }
+        // a duplicate, already listed
+        if difference == 0 && jolt != device {
+            continue;
+        }
+        // stepping the largest amount allowed needs the fewest extra adapters
+        let mut filler = current + max_gap;
+        while filler < jolt {
+            diagnosis.missing.push(filler);
+            filler += max_gap;
+        }
+        current = jolt;
+    }
+    diagnosis
 }
 
-fn part1(input: &str) -> Result<Distribution, Box<dyn Error>> {
-    let mut adapters = input
+/// Without a device rating the device is rated the most an adapter can handle above the
+/// largest one. Steps too large to take are still counted, and the diagnosis alongside the
+/// distribution says what to change when the adapters can't all be chained.
+fn part1(
+    input: &str,
+    outlet: i32,
+    device: Option<i32>,
+    max_gap: i32,
+) -> Result<(Distribution, Diagnosis), Box<dyn Error>> {
+    let adapters = input
         .lines()
         .map(|line| Adapter::from_line(line))
         .collect::<Result<Vec<Adapter>, UnsolvedError>>()?;
 
-    let device_jolt = adapters
-        .iter()
-        .map(|adapter| adapter.jolt)
-        .max()
-        .ok_or(UnsolvedError)?
-        + 3;
-
-    // start it from the charging outlet
-    // let mut seen: HashMap<String, Result<Vec<Adapter>, UnsolvedError>> = HashMap::new();
-    // let answer = part1_recursion(&mut adapters, 0, device_jolt, &mut seen)?;
-    let answer = part1_greedy(&adapters, device_jolt)?;
-
-    let mut distribution = Distribution::new();
-    // always add one to the one difference since we start at the 0 port
-    distribution.one += 1;
-    // always add one to the three difference since that's the final port
-    distribution.three += 1;
-
-    for i in 0..answer.len() - 1 {
-        let current = answer.get(i).ok_or(UnsolvedError)?;
-        let next = answer.get(i + 1).ok_or(UnsolvedError)?;
-        let difference = next.jolt - current.jolt;
-        match difference {
-            1 => distribution.one += 1,
-            2 => distribution.two += 1,
-            3 => distribution.three += 1,
-            _ => return Err(Box::new(UnsolvedError)),
+    let device_jolt = match device {
+        Some(jolt) => jolt,
+        None => {
+            adapters
+                .iter()
+                .map(|adapter| adapter.jolt)
+                .max()
+                .ok_or(UnsolvedError)?
+                + max_gap
         }
-    }
+    };
+
+    let distribution = Distribution::new(&adapters, outlet, device_jolt, max_gap);
+    let diagnosis = diagnose(&adapters, outlet, device_jolt, max_gap);
 
-    println!("part1: {}", distribution.one * distribution.three);
+    println!("part1: {}", distribution.count(1) * distribution.count(3));
 
-    Ok(distribution)
+    Ok((distribution, diagnosis))
 }
 
-fn key(adapters: &Vec<Adapter>, starting_jolt: i32) -> String {
-    let jolts = adapters.iter().map(|a| a.jolt).sorted().join(":");
-    format!("{:?}_{:?}", jolts, starting_jolt)
+/// How many chains lead from each rating to the device, computed once over the sorted
+/// ratings in O(n * max_gap). Chains go from the outlet through strictly increasing
+/// adapters, each at most `max_gap` jolts above the previous one, to the device which is
+/// rated `max_gap` above the largest adapter.
+struct Arrangements {
+    // the outlet, every adapter in order, then the device
+    jolts: Vec<i32>,
+    // ways[i] is the number of chains from jolts[i] to the device
+    ways: Vec<BigUint>,
+    max_gap: i32,
 }
 
-fn part2_recursion(
-    adapters: &Vec<Adapter>,
-    starting_jolt: i32,
-    seen: &mut HashMap<String, u64>,
-    visited: &mut VecDeque<i32>,
-) -> u64 {
-    let key = key(adapters, starting_jolt);
+impl Arrangements {
+    fn new(adapters: &[Adapter], max_gap: i32) -> Arrangements {
+        let mut jolts: Vec<i32> = adapters.iter().map(|adapter| adapter.jolt).collect();
+        jolts.push(0);
+        jolts.sort_unstable();
+        jolts.push(jolts[jolts.len() - 1] + max_gap);
+
+        let mut ways = vec![BigUint::zero(); jolts.len()];
+        ways[jolts.len() - 1] = BigUint::one();
+        for i in (0..jolts.len() - 1).rev() {
+            let mut total = BigUint::zero();
+            for j in i + 1..jolts.len() {
+                let difference = jolts[j] - jolts[i];
+                if difference > max_gap {
+                    break;
+                }
+                // two adapters with the same rating can't follow each other
+                if difference > 0 {
+                    total += &ways[j];
+                }
+            }
+            ways[i] = total;
+        }
 
-    if starting_jolt == 0 {
-        return 1;
+        Arrangements {
+            jolts,
+            ways,
+            max_gap,
+        }
     }
 
-    if seen.contains_key(key.as_str()) {
-        return *seen.get(key.as_str()).unwrap();
+    fn count(&self) -> &BigUint {
+        &self.ways[0]
     }
 
-    let possible_adapters: Vec<&Adapter> = adapters
-        .iter()
-        .filter(|adapter| adapter.jolt < starting_jolt && adapter.jolt >= starting_jolt - 3)
-        .collect();
+    fn device(&self) -> usize {
+        self.jolts.len() - 1
+    }
 
-    let mut answers = 0;
+    // the positions that can follow the one at from and still reach the device, in order
+    fn steps(&self, from: usize) -> impl Iterator<Item = usize> + '_ {
+        let jolt = self.jolts[from];
+        (from + 1..self.jolts.len())
+            .take_while(move |&to| self.jolts[to] - jolt <= self.max_gap)
+            .filter(move |&to| self.jolts[to] > jolt && !self.ways[to].is_zero())
+    }
 
-    for possible_adapter in possible_adapters {
-        if visited.contains(&possible_adapter.jolt) {
-            continue;
+    fn adapters(&self, path: &[usize]) -> Vec<Adapter> {
+        // leave out the outlet and the device
+        path[1..path.len() - 1]
+            .iter()
+            .map(|&position| Adapter {
+                jolt: self.jolts[position],
+            })
+            .collect()
+    }
+
+    /// Every chain in lexicographic order, found one at a time. Dead ends are never
+    /// explored since the counts say which steps can still reach the device.
+    fn chains(&self) -> Chains<'_> {
+        Chains {
+            arrangements: self,
+            path: Vec::new(),
+            finished: self.count().is_zero(),
         }
+    }
 
-        visited.push_front(possible_adapter.jolt);
-        answers += part2_recursion(adapters, possible_adapter.jolt, seen, visited);
-        visited.pop_front();
+    /// Draws one of the chains, each with the same probability, by taking every step with
+    /// probability proportional to the number of chains through it.
+    fn sample(&self, random: &mut Random) -> Option<Vec<Adapter>> {
+        if self.count().is_zero() {
+            return None;
+        }
+        let mut path = vec![0];
+        let mut current = 0;
+        while current != self.device() {
+            let mut choice = random.below(&self.ways[current]);
+            for next in self.steps(current) {
+                if choice < self.ways[next] {
+                    current = next;
+                    break;
+                }
+                choice -= &self.ways[next];
+            }
+            path.push(current);
+        }
+        Some(self.adapters(&path))
     }
+}
+
+struct Chains<'a> {
+    arrangements: &'a Arrangements,
+    // positions of the chain last returned, from the outlet to the device
+    path: Vec<usize>,
+    finished: bool,
+}
+
+impl<'a> Chains<'a> {
+    // follows the lowest steps from the end of the path until it reaches the device
+    fn complete(&mut self) {
+        let device = self.arrangements.device();
+        while let Some(&last) = self.path.last() {
+            if last == device {
+                break;
+            }
+            // always some step since the counts say the device is reachable
+            let next = self.arrangements.steps(last).next().unwrap();
+            self.path.push(next);
+        }
+    }
+}
 
-    seen.insert(key, answers);
+impl<'a> Iterator for Chains<'a> {
+    type Item = Vec<Adapter>;
 
-    return answers;
+    fn next(&mut self) -> Option<Vec<Adapter>> {
+        if self.finished {
+            return None;
+        }
+        if self.path.is_empty() {
+            self.path.push(0);
+        } else {
+            // replace the deepest step that has a higher alternative
+            loop {
+                let taken = self.path.pop().unwrap();
+                let previous = match self.path.last() {
+                    Some(&previous) => previous,
+                    None => {
+                        self.finished = true;
+                        return None;
+                    }
+                };
+                if let Some(next) = self.arrangements.steps(previous).find(|&next| next > taken) {
+                    self.path.push(next);
+                    break;
+                }
+            }
+        }
+        self.complete();
+        Some(self.arrangements.adapters(&self.path))
+    }
 }
 
-fn part2(input: &str) -> Result<u64, Box<dyn Error>> {
-    let mut adapters = input
+/// A xorshift64* generator; plenty for picking chains and needs no dependencies.
+struct Random {
+    state: u64,
+}
+
+impl Random {
+    fn new(seed: u64) -> Random {
+        // the state must never be zero
+        Random {
+            state: (seed ^ 0x9e37_79b9_7f4a_7c15).max(1),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // uniform in 0..bound by rejecting draws with the right number of bits that are too big
+    fn below(&mut self, bound: &BigUint) -> BigUint {
+        let bits = bound.bits() as usize;
+        loop {
+            let mut bytes: Vec<u8> = Vec::new();
+            while bytes.len() * 8 < bits {
+                bytes.extend_from_slice(&self.next_u64().to_le_bytes());
+            }
+            bytes.truncate(bits.div_ceil(8));
+            if !bits.is_multiple_of(8) {
+                let last = bytes.len() - 1;
+                bytes[last] &= (1u8 << (bits % 8)) - 1;
+            }
+            let candidate = BigUint::from_bytes_le(&bytes);
+            if candidate < *bound {
+                return candidate;
+            }
+        }
+    }
+}
+
+fn part2(input: &str, max_gap: i32) -> Result<BigUint, Box<dyn Error>> {
+    let adapters = input
         .lines()
         .map(|line| Adapter::from_line(line))
         .collect::<Result<Vec<Adapter>, UnsolvedError>>()?;
 
-    // add the starting adapter
-    adapters.push(Adapter { jolt: 0 });
-
-    let final_jolt = adapters
-        .iter()
-        .map(|adapter| adapter.jolt)
-        .max()
-        .ok_or(UnsolvedError)?
-        + 3;
-
-    let mut seen = HashMap::new();
-    let mut visited = VecDeque::new();
-    let answer = part2_recursion(&adapters, final_jolt, &mut seen, &mut visited);
+    let answer = Arrangements::new(&adapters, max_gap).count().clone();
 
     println!("part2: {}", answer);
 
@@ -176,17 +418,66 @@ fn part2(input: &str) -> Result<u64, Box<dyn Error>> {
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
+    const MAX_GAP: i32 = 3;
+
     let input = include_str!("day10_1.txt");
-    part1(input)?;
 
-    part2(input)?;
+    // chains <n> lists the first n chains, sample <n> [seed] draws n at random
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(command) = args.first() {
+        let adapters = input
+            .lines()
+            .map(Adapter::from_line)
+            .collect::<Result<Vec<Adapter>, UnsolvedError>>()?;
+        let arrangements = Arrangements::new(&adapters, MAX_GAP);
+        let amount: usize = args.get(1).map(|n| n.parse()).transpose()?.unwrap_or(10);
+        let format = |chain: Vec<Adapter>| chain.iter().map(|a| a.jolt).join(" ");
+
+        match command.as_str() {
+            "chains" => {
+                for chain in arrangements.chains().take(amount) {
+                    println!("{}", format(chain));
+                }
+            }
+            "sample" => {
+                let seed = match args.get(2) {
+                    Some(seed) => seed.parse()?,
+                    None => SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos() as u64,
+                };
+                let mut random = Random::new(seed);
+                for _ in 0..amount {
+                    let chain = arrangements.sample(&mut random).ok_or(UnsolvedError)?;
+                    println!("{}", format(chain));
+                }
+            }
+            other => {
+                return Err(format!(
+                    "unknown command {}, usage: day10 [chains <n> | sample <n> [seed]]",
+                    other
+                )
+                .into())
+            }
+        }
+        return Ok(());
+    }
+
+    let (_, diagnosis) = part1(input, 0, None, MAX_GAP)?;
+    if !diagnosis.is_feasible() {
+        println!("{}", diagnosis);
+    }
+
+    part2(input, MAX_GAP)?;
 
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{part1, part2, Distribution};
+    use crate::{
+        diagnose, part1, part2, Adapter, Arrangements, Break, Diagnosis, Distribution, Random,
+    };
+    use num::BigUint;
+    use std::collections::HashMap;
 
     #[test]
     fn part1_small_test() {
@@ -201,16 +492,14 @@ mod tests {
 6
 12
 4";
-        let answer = part1(sample);
+        let answer = part1(sample, 0, None, 3);
         assert!(answer.is_ok());
-        assert_eq!(
-            answer.unwrap(),
-            Distribution {
-                one: 7,
-                two: 0,
-                three: 5
-            }
-        )
+        let (distribution, diagnosis) = answer.unwrap();
+        assert!(diagnosis.is_feasible());
+        assert_eq!(distribution.count(1), 7);
+        assert_eq!(distribution.count(2), 0);
+        assert_eq!(distribution.count(3), 5);
+        assert!(distribution.too_large.is_empty());
     }
 
     #[test]
@@ -246,16 +535,14 @@ mod tests {
 34
 10
 3";
-        let answer = part1(sample);
+        let answer = part1(sample, 0, None, 3);
         assert!(answer.is_ok());
-        assert_eq!(
-            answer.unwrap(),
-            Distribution {
-                one: 22,
-                two: 0,
-                three: 10
-            }
-        )
+        let (distribution, diagnosis) = answer.unwrap();
+        assert!(diagnosis.is_feasible());
+        assert_eq!(distribution.count(1), 22);
+        assert_eq!(distribution.count(2), 0);
+        assert_eq!(distribution.count(3), 10);
+        assert!(distribution.too_large.is_empty());
     }
 
     #[test]
@@ -271,9 +558,9 @@ mod tests {
 6
 12
 4";
-        let answer = part2(sample);
+        let answer = part2(sample, 3);
         assert!(answer.is_ok());
-        assert_eq!(answer.unwrap(), 8);
+        assert_eq!(answer.unwrap(), BigUint::from(8u32));
     }
 
     #[test]
@@ -309,8 +596,139 @@ mod tests {
 34
 10
 3";
-        let answer = part2(sample);
+        let answer = part2(sample, 3);
         assert!(answer.is_ok());
-        assert_eq!(answer.unwrap(), 19208);
+        assert_eq!(answer.unwrap(), BigUint::from(19208u32));
+    }
+
+    #[test]
+    fn arrangements_with_other_gaps() {
+        let adapters: Vec<Adapter> = (1..=200).map(|jolt| Adapter { jolt }).collect();
+        // with a gap of 1 every adapter has to be used
+        assert_eq!(
+            *Arrangements::new(&adapters, 1).count(),
+            BigUint::from(1u32)
+        );
+        // the device is at 400 so only 200 has to be used, far past what a u64 holds
+        assert_eq!(
+            *Arrangements::new(&adapters, 200).count(),
+            BigUint::from(2u32).pow(199)
+        );
+        // 1 and 4 can't be joined when the gap is 2
+        let adapters = vec![Adapter { jolt: 1 }, Adapter { jolt: 4 }];
+        assert_eq!(
+            *Arrangements::new(&adapters, 2).count(),
+            BigUint::from(0u32)
+        );
+    }
+
+    #[test]
+    fn chains_and_samples() {
+        let adapters: Vec<Adapter> = [16, 10, 15, 5, 1, 11, 7, 19, 6, 12, 4]
+            .iter()
+            .map(|&jolt| Adapter { jolt })
+            .collect();
+        let arrangements = Arrangements::new(&adapters, 3);
+
+        let chains: Vec<Vec<i32>> = arrangements
+            .chains()
+            .map(|chain| chain.iter().map(|adapter| adapter.jolt).collect())
+            .collect();
+        assert_eq!(chains.len(), 8);
+        assert_eq!(chains[0], vec![1, 4, 5, 6, 7, 10, 11, 12, 15, 16, 19]);
+        assert_eq!(chains[7], vec![1, 4, 7, 10, 12, 15, 16, 19]);
+        let mut sorted = chains.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(chains, sorted);
+
+        let mut random = Random::new(2020);
+        let mut drawn: HashMap<Vec<i32>, usize> = HashMap::new();
+        for _ in 0..8000 {
+            let chain = arrangements.sample(&mut random).unwrap();
+            *drawn
+                .entry(chain.iter().map(|adapter| adapter.jolt).collect())
+                .or_insert(0) += 1;
+        }
+        assert_eq!(drawn.len(), 8);
+        assert!(drawn.values().all(|&count| count > 850 && count < 1150));
+
+        let impossible = Arrangements::new(&[Adapter { jolt: 5 }], 3);
+        assert_eq!(impossible.chains().next(), None);
+        assert_eq!(impossible.sample(&mut random), None);
+    }
+
+    #[test]
+    fn diagnosis() {
+        let adapters: Vec<Adapter> = [1, 2, 9, 5, 2, 5, 16]
+            .iter()
+            .map(|&jolt| Adapter { jolt })
+            .collect();
+        let diagnosis = diagnose(&adapters, 0, 19, 3);
+        assert_eq!(
+            diagnosis,
+            Diagnosis {
+                chain_break: Some(Break {
+                    jolt: 2,
+                    nearest: 2
+                }),
+                duplicates: vec![2, 5],
+                unusable: vec![],
+                missing: vec![8, 12, 15],
+            }
+        );
+        assert_eq!(
+            diagnosis.to_string(),
+            "the chain breaks at 2 jolts, the nearest remaining adapter is 2; \
+             remove the duplicate adapters 2, 5; add adapters 8, 12, 15"
+        );
+        assert!(diagnose(&adapters[..2], 0, 5, 3).is_feasible());
+
+        let (distribution, diagnosis) = part1("4\n8", 0, None, 3).unwrap();
+        assert_eq!(distribution.too_large, vec![(0, 4), (4, 8)]);
+        assert!(!diagnosis.is_feasible());
+        assert_eq!(part1("3\n5", 1, Some(7), 3).unwrap().0.count(2), 3);
+        assert_eq!(
+            diagnose(&[Adapter { jolt: 4 }, Adapter { jolt: 8 }], 0, 11, 3).to_string(),
+            "the chain breaks at 0 jolts, the nearest remaining adapter is 4; add adapters 3, 7"
+        );
+        // a different outlet and a device too far above the last adapter
+        assert_eq!(
+            diagnose(&[Adapter { jolt: 4 }, Adapter { jolt: 5 }], 2, 12, 3).to_string(),
+            "the chain breaks at 5 jolts, the nearest remaining adapter is 12; add adapters 8, 11"
+        );
+        // adapters below the outlet are no stepping stone for the ones above
+        let below = diagnose(&[Adapter { jolt: 1 }, Adapter { jolt: 6 }], 5, 9, 3);
+        assert!(below.missing.is_empty());
+        assert_eq!(
+            below.to_string(),
+            "every other adapter can be chained; remove the adapters at or below the outlet 1"
+        );
+        let at_outlet: Vec<Adapter> = [5, 5, 6].iter().map(|&jolt| Adapter { jolt }).collect();
+        let at_outlet = diagnose(&at_outlet, 5, 9, 3);
+        assert_eq!(at_outlet.duplicates, vec![5]);
+        assert_eq!(at_outlet.unusable, vec![5]);
+        assert!(!at_outlet.is_feasible());
+    }
+
+    #[test]
+    fn distribution_with_other_ratings() {
+        let adapters: Vec<Adapter> = [3, 5, 6, 12].iter().map(|&jolt| Adapter { jolt }).collect();
+        let distribution = Distribution::new(&adapters, 2, 20, 4);
+        assert_eq!(distribution.count(1), 2);
+        assert_eq!(distribution.count(2), 1);
+        assert_eq!(distribution.count(6), 1);
+        assert_eq!(distribution.count(8), 1);
+        assert_eq!(distribution.too_large, vec![(6, 12), (12, 20)]);
+        assert_eq!(
+            distribution.to_string(),
+            "1 jolt differences: 2
+2 jolt differences: 1
+6 jolt differences: 1
+8 jolt differences: 1
+6 to 12 is too large a step
+12 to 20 is too large a step
+"
+        );
     }
 }
@@ -1,10 +1,17 @@
-use advent_of_code_2020::UnsolvedError;
-use itertools::Itertools;
+#![feature(str_split_once)]
+
+use advent_of_code_2020::{AdventOfCodeError, UnsolvedError};
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
 use std::fmt::{format, Formatter};
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum Status {
     FLOOR,
     OCCUPIED,
@@ -39,6 +46,77 @@ struct Position {
     status: Status,
 }
 
+/// Which seats count as a seat's neighbours.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Neighbourhood {
+    // the eight seats around it
+    Moore,
+    // the four seats above, below, left and right of it
+    VonNeumann,
+    // the first seat in each of the eight directions, looking past the floor
+    LineOfSight,
+}
+
+/// How seats change each generation, written like Life rules: `moore B0/S0123` means an
+/// empty seat is taken when none of its Moore neighbours are occupied, and an occupied
+/// seat stays taken while 0 to 3 of them are. Floor never changes.
+#[derive(Debug, PartialEq, Clone)]
+struct Rule {
+    neighbourhood: Neighbourhood,
+    // occupied neighbour counts that make an empty seat occupied
+    birth: Vec<usize>,
+    // occupied neighbour counts that keep an occupied seat occupied
+    survival: Vec<usize>,
+}
+
+impl Rule {
+    const PART1: &'static str = "moore B0/S0123";
+    const PART2: &'static str = "line-of-sight B0/S01234";
+
+    fn next_status(&self, status: &Status, occupied_neighbours: usize) -> Status {
+        match status {
+            Status::FLOOR => Status::FLOOR,
+            Status::EMPTY if self.birth.contains(&occupied_neighbours) => Status::OCCUPIED,
+            Status::EMPTY => Status::EMPTY,
+            Status::OCCUPIED if self.survival.contains(&occupied_neighbours) => Status::OCCUPIED,
+            Status::OCCUPIED => Status::EMPTY,
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = AdventOfCodeError;
+
+    fn from_str(description: &str) -> Result<Rule, AdventOfCodeError> {
+        let error = || {
+            AdventOfCodeError::Custom(format!(
+                "expected <moore|von-neumann|line-of-sight> B<counts>/S<counts> but found {}",
+                description
+            ))
+        };
+        let counts = |digits: &str| -> Result<Vec<usize>, AdventOfCodeError> {
+            digits
+                .chars()
+                .map(|c| c.to_digit(10).map(|d| d as usize).ok_or_else(error))
+                .collect()
+        };
+
+        let (neighbourhood, thresholds) = description.trim().split_once(' ').ok_or_else(error)?;
+        let neighbourhood = match neighbourhood {
+            "moore" => Neighbourhood::Moore,
+            "von-neumann" => Neighbourhood::VonNeumann,
+            "line-of-sight" => Neighbourhood::LineOfSight,
+            _ => return Err(error()),
+        };
+        let (birth, survival) = thresholds.trim().split_once('/').ok_or_else(error)?;
+        Ok(Rule {
+            neighbourhood,
+            birth: counts(birth.strip_prefix('B').ok_or_else(error)?)?,
+            survival: counts(survival.strip_prefix('S').ok_or_else(error)?)?,
+        })
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 struct Airplane {
     positions: Vec<Position>,
@@ -93,11 +171,8 @@ impl Airplane {
         })
     }
 
-    fn key(&self) -> String {
-        self.positions
-            .iter()
-            .map(|position| format!("{}", position.status))
-            .join("")
+    fn index(&self, position: &Position) -> usize {
+        ((self.columns * position.row) + position.column) as usize
     }
 
     fn right(&self, position: &Position) -> Option<&Position> {
@@ -208,6 +283,18 @@ impl Airplane {
         .collect()
     }
 
+    fn orthogonal_seats(&self, position: &Position) -> Vec<&Position> {
+        vec![
+            self.right(position),
+            self.left(position),
+            self.down(position),
+            self.up(position),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
     fn adjacent_loose_seats<'m>(&'m self, position: &'m Position) -> Vec<&'m Position> {
         vec![
             self.first_non_empty(position, Airplane::right),
@@ -225,130 +312,288 @@ impl Airplane {
     }
 }
 
-fn part1(input: &str) -> Result<usize, Box<dyn Error>> {
-    let mut airplane = Airplane::from_input(input)?;
-    println!(
-        "airplane with rows: {} & columns: {}",
-        airplane.rows, airplane.columns
-    );
-    loop {
-        // helpful for debugging
-        // println!("{}", airplane);
-        let mut new_positions = Airplane {
-            positions: vec![],
-            rows: airplane.rows,
-            columns: airplane.columns,
-        };
+impl Airplane {
+    fn neighbours<'m>(
+        &'m self,
+        position: &'m Position,
+        neighbourhood: Neighbourhood,
+    ) -> Vec<&'m Position> {
+        match neighbourhood {
+            Neighbourhood::Moore => self.adjacent_seats(position),
+            Neighbourhood::VonNeumann => self.orthogonal_seats(position),
+            Neighbourhood::LineOfSight => self.adjacent_loose_seats(position),
+        }
+    }
+}
+
+/// Every seat's neighbours stored back to back, found once for a layout so that
+/// generations only have to look up indices.
+struct Neighbours {
+    // the neighbours of position i are indices[starts[i]..starts[i + 1]]
+    starts: Vec<usize>,
+    indices: Vec<u32>,
+}
+
+impl Neighbours {
+    fn new(airplane: &Airplane, neighbourhood: Neighbourhood) -> Neighbours {
+        let mut starts = Vec::with_capacity(airplane.positions.len() + 1);
+        let mut indices = Vec::new();
+        starts.push(0);
         for position in &airplane.positions {
-            match position.status {
-                Status::FLOOR => {}
-                Status::OCCUPIED => {
-                    let adjacent = airplane.adjacent_seats(position);
-                    let four_or_more = adjacent
-                        .iter()
-                        .filter(|seat| seat.status == Status::OCCUPIED)
-                        .count();
-                    if four_or_more >= 4 {
-                        let mut new_position = position.clone();
-                        new_position.status = Status::EMPTY;
-                        new_positions.positions.push(new_position);
-                        continue;
-                    }
-                }
-                Status::EMPTY => {
-                    let adjacent = airplane.adjacent_seats(position);
-                    let any_occupied = adjacent.iter().any(|seat| seat.status == Status::OCCUPIED);
-                    if !any_occupied {
-                        let mut new_position = position.clone();
-                        new_position.status = Status::OCCUPIED;
-                        new_positions.positions.push(new_position);
-                        continue;
-                    }
-                }
+            // floor never changes and is never occupied, so it needs no neighbours and
+            // isn't one
+            if position.status != Status::FLOOR {
+                indices.extend(
+                    airplane
+                        .neighbours(position, neighbourhood)
+                        .into_iter()
+                        .filter(|seat| seat.status != Status::FLOOR)
+                        .map(|seat| airplane.index(seat) as u32),
+                );
             }
-            // if none of the rules hit, then add the seat as it was
-            new_positions.positions.push(position.clone());
+            starts.push(indices.len());
         }
+        Neighbours { starts, indices }
+    }
 
-        // if the iterations have stabilized
-        if airplane.key() == new_positions.key() {
-            break;
+    fn of(&self, index: usize) -> &[u32] {
+        &self.indices[self.starts[index]..self.starts[index + 1]]
+    }
+}
+
+/// A rule running over a layout. Each generation is written into the spare buffer, which
+/// then becomes the current one.
+struct Simulation {
+    columns: i32,
+    rule: Rule,
+    neighbours: Neighbours,
+    current: Vec<Status>,
+    next: Vec<Status>,
+    generation: usize,
+}
+
+impl fmt::Display for Simulation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for row in self.current.chunks(self.columns as usize) {
+            for status in row {
+                write!(f, "{}", status)?;
+            }
+            writeln!(f)?;
         }
+        Ok(())
+    }
+}
 
-        // move to the next iteration
-        airplane = new_positions;
+impl Simulation {
+    fn new(airplane: &Airplane, rule: &Rule) -> Simulation {
+        let current: Vec<Status> = airplane
+            .positions
+            .iter()
+            .map(|position| position.status)
+            .collect();
+        Simulation {
+            columns: airplane.columns,
+            rule: rule.clone(),
+            neighbours: Neighbours::new(airplane, rule.neighbourhood),
+            next: current.clone(),
+            current,
+            generation: 0,
+        }
     }
 
-    let answer = airplane
-        .positions
-        .iter()
-        .filter(|position| position.status == Status::OCCUPIED)
-        .count();
+    /// Moves to the next generation, returning whether any seat changed.
+    fn step(&mut self) -> bool {
+        let mut changed = false;
+        for (index, status) in self.current.iter().enumerate() {
+            let occupied = self
+                .neighbours
+                .of(index)
+                .iter()
+                .filter(|&&neighbour| self.current[neighbour as usize] == Status::OCCUPIED)
+                .count();
+            let next = self.rule.next_status(status, occupied);
+            changed |= next != *status;
+            self.next[index] = next;
+        }
+        std::mem::swap(&mut self.current, &mut self.next);
+        if changed {
+            self.generation += 1;
+        }
+        changed
+    }
 
-    println!("part1: {}", answer);
+    fn occupied(&self) -> usize {
+        self.current
+            .iter()
+            .filter(|&&status| status == Status::OCCUPIED)
+            .count()
+    }
+}
 
-    Ok(answer)
+/// How a simulation ended.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Outcome {
+    // nothing changes after this generation
+    Stabilized { generation: usize },
+    // the layout at generation start comes back every period generations
+    Oscillating { start: usize, period: usize },
+    // gave up after this many generations without either happening
+    LimitReached { generations: usize },
 }
 
-fn part2(input: &str) -> Result<usize, Box<dyn Error>> {
-    let mut airplane = Airplane::from_input(input)?;
-    println!(
-        "airplane with rows: {} & columns: {}",
-        airplane.rows, airplane.columns
-    );
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Stabilized { generation } => {
+                write!(f, "stabilized after {} generations", generation)
+            }
+            Outcome::Oscillating { start, period } => write!(
+                f,
+                "oscillating every {} generations from generation {}",
+                period, start
+            ),
+            Outcome::LimitReached { generations } => {
+                write!(f, "still changing after {} generations", generations)
+            }
+        }
+    }
+}
+
+/// Runs a rule until the seating stops changing, a layout seen before comes back, or
+/// max_generations have passed. Every generation, starting with the initial layout, is
+/// handed to on_generation.
+///
+/// Every layout is remembered in full so that a repeat is never mistaken for one, which
+/// costs a copy of the seating per generation.
+fn simulate<F: FnMut(&Simulation)>(
+    airplane: &Airplane,
+    rule: &Rule,
+    max_generations: Option<usize>,
+    mut on_generation: F,
+) -> (Simulation, Outcome) {
+    let mut simulation = Simulation::new(airplane, rule);
+    let mut seen: HashMap<Vec<Status>, usize> = HashMap::new();
+    seen.insert(simulation.current.clone(), 0);
     loop {
-        // helpful for debugging
-        println!("{}", airplane);
-        let mut new_positions = Airplane {
-            positions: vec![],
-            rows: airplane.rows,
-            columns: airplane.columns,
-        };
-        for position in &airplane.positions {
-            match position.status {
-                Status::FLOOR => {}
-                Status::OCCUPIED => {
-                    let adjacent = airplane.adjacent_loose_seats(position);
-                    let five_or_more = adjacent
-                        .iter()
-                        .filter(|seat| seat.status == Status::OCCUPIED)
-                        .count();
-                    if five_or_more >= 5 {
-                        let mut new_position = position.clone();
-                        new_position.status = Status::EMPTY;
-                        new_positions.positions.push(new_position);
-                        continue;
-                    }
-                }
-                Status::EMPTY => {
-                    let adjacent = airplane.adjacent_loose_seats(position);
-                    let any_occupied = adjacent.iter().any(|seat| seat.status == Status::OCCUPIED);
-                    if !any_occupied {
-                        let mut new_position = position.clone();
-                        new_position.status = Status::OCCUPIED;
-                        new_positions.positions.push(new_position);
-                        continue;
-                    }
-                }
+        on_generation(&simulation);
+        if max_generations == Some(simulation.generation) {
+            let generations = simulation.generation;
+            return (simulation, Outcome::LimitReached { generations });
+        }
+        if !simulation.step() {
+            let generation = simulation.generation;
+            return (simulation, Outcome::Stabilized { generation });
+        }
+        let generation = simulation.generation;
+        if let Some(start) = seen.insert(simulation.current.clone(), generation) {
+            let period = generation - start;
+            return (simulation, Outcome::Oscillating { start, period });
+        }
+    }
+}
+
+/// How many seats end up occupied once the rule settles the seating.
+fn settle<F: FnMut(&Simulation)>(
+    airplane: &Airplane,
+    rule: &Rule,
+    on_generation: F,
+) -> Result<usize, Box<dyn Error>> {
+    match simulate(airplane, rule, None, on_generation) {
+        (simulation, Outcome::Stabilized { .. }) => Ok(simulation.occupied()),
+        (_, outcome) => Err(Box::new(AdventOfCodeError::Custom(format!(
+            "the seating never settles, it is {}",
+            outcome
+        )))),
+    }
+}
+
+// floor, empty and occupied seats in images
+const FLOOR_COLOUR: [u8; 3] = [40, 40, 40];
+const EMPTY_COLOUR: [u8; 3] = [60, 180, 75];
+const OCCUPIED_COLOUR: [u8; 3] = [230, 25, 75];
+
+fn write_text_frame<W: Write>(simulation: &Simulation, writer: &mut W) -> io::Result<()> {
+    writeln!(writer, "generation {}", simulation.generation)?;
+    writeln!(writer, "{}", simulation)
+}
+
+/// Draws the seating as a binary PPM image with every seat a square of scale pixels.
+fn write_ppm<W: Write>(simulation: &Simulation, scale: usize, writer: &mut W) -> io::Result<()> {
+    let columns = simulation.columns as usize;
+    let rows = simulation.current.len() / columns;
+    write!(writer, "P6\n{} {}\n255\n", columns * scale, rows * scale)?;
+
+    let mut line = Vec::with_capacity(columns * scale * 3);
+    for row in simulation.current.chunks(columns) {
+        line.clear();
+        for status in row {
+            let colour = match status {
+                Status::FLOOR => FLOOR_COLOUR,
+                Status::EMPTY => EMPTY_COLOUR,
+                Status::OCCUPIED => OCCUPIED_COLOUR,
+            };
+            for _ in 0..scale {
+                line.extend_from_slice(&colour);
             }
-            // if none of the rules hit, then add the seat as it was
-            new_positions.positions.push(position.clone());
         }
+        for _ in 0..scale {
+            writer.write_all(&line)?;
+        }
+    }
+    Ok(())
+}
+
+/// Saves every generation of a simulation so it can be watched converging.
+enum Exporter {
+    // all generations in one text file, one after the other
+    Text(BufWriter<File>),
+    // an image per generation in a directory, which tools such as ffmpeg can animate
+    Ppm { directory: PathBuf, scale: usize },
+}
 
-        // if the iterations have stabilized
-        if airplane.key() == new_positions.key() {
-            break;
+impl Exporter {
+    fn export(&mut self, simulation: &Simulation) -> io::Result<()> {
+        match self {
+            Exporter::Text(writer) => write_text_frame(simulation, writer),
+            Exporter::Ppm { directory, scale } => {
+                let path = directory.join(format!("generation_{:04}.ppm", simulation.generation));
+                let mut writer = BufWriter::new(File::create(path)?);
+                write_ppm(simulation, *scale, &mut writer)?;
+                writer.flush()
+            }
         }
+    }
 
-        // move to the next iteration
-        airplane = new_positions;
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Exporter::Text(mut writer) => writer.flush(),
+            Exporter::Ppm { .. } => Ok(()),
+        }
     }
+}
+
+fn part1(input: &str) -> Result<usize, Box<dyn Error>> {
+    let airplane = Airplane::from_input(input)?;
+    println!(
+        "airplane with rows: {} & columns: {}",
+        airplane.rows, airplane.columns
+    );
+    let rule: Rule = Rule::PART1.parse()?;
+    let answer = settle(&airplane, &rule, |_| {})?;
+
+    println!("part1: {}", answer);
 
-    let answer = airplane
-        .positions
-        .iter()
-        .filter(|position| position.status == Status::OCCUPIED)
-        .count();
+    Ok(answer)
+}
+
+fn part2(input: &str) -> Result<usize, Box<dyn Error>> {
+    let airplane = Airplane::from_input(input)?;
+    println!(
+        "airplane with rows: {} & columns: {}",
+        airplane.rows, airplane.columns
+    );
+    let rule: Rule = Rule::PART2.parse()?;
+    let answer = settle(&airplane, &rule, |_| {})?;
 
     println!("part2: {}", answer);
 
@@ -357,6 +602,59 @@ fn part2(input: &str) -> Result<usize, Box<dyn Error>> {
 
 fn main() -> Result<(), Box<dyn Error>> {
     let input = include_str!("day11_1.txt");
+
+    // export text <file> [rule] or export ppm <directory> [rule] saves every generation
+    // of the part 2 rule, or the one given
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("export") {
+        let usage =
+            || AdventOfCodeError::Custom("usage: day11 export text|ppm <path> [rule]".into());
+        let (format, path) = match (args.get(1), args.get(2)) {
+            (Some(format), Some(path)) => (format.as_str(), PathBuf::from(path)),
+            _ => return Err(Box::new(usage())),
+        };
+        let mut exporter = match format {
+            "text" => Exporter::Text(BufWriter::new(File::create(&path)?)),
+            "ppm" => {
+                std::fs::create_dir_all(&path)?;
+                Exporter::Ppm {
+                    directory: path,
+                    scale: 4,
+                }
+            }
+            _ => return Err(Box::new(usage())),
+        };
+        let rule: Rule = args.get(3).map_or(Rule::PART2, String::as_str).parse()?;
+
+        // stop writing after the first failure but still report it
+        let mut written = Ok(());
+        let (_, outcome) = simulate(&Airplane::from_input(input)?, &rule, None, |simulation| {
+            if written.is_ok() {
+                written = exporter.export(simulation);
+            }
+        });
+        written?;
+        exporter.finish()?;
+        println!("{}", outcome);
+        return Ok(());
+    }
+
+    // any other rule can be run by describing it, optionally stopping after a number of
+    // generations, e.g. day11 "von-neumann B0/S012" 100
+    if let Some(description) = args.first() {
+        let rule: Rule = description.parse()?;
+        let max_generations = args.get(1).map(|n| n.parse()).transpose()?;
+        let (simulation, outcome) = simulate(
+            &Airplane::from_input(input)?,
+            &rule,
+            max_generations,
+            |_| {},
+        );
+        println!("{}occupied: {}", simulation, simulation.occupied());
+        println!("{}", outcome);
+        return Ok(());
+    }
+
     part1(input)?;
 
     part2(input)?;
@@ -366,7 +664,10 @@ fn main() -> Result<(), Box<dyn Error>> {
 
 #[cfg(test)]
 mod tests {
-    use crate::{part1, part2};
+    use crate::{
+        part1, part2, simulate, write_ppm, write_text_frame, Airplane, Neighbourhood, Neighbours,
+        Outcome, Rule, EMPTY_COLOUR, OCCUPIED_COLOUR,
+    };
 
     #[test]
     fn part1_test() {
@@ -403,4 +704,85 @@ L.LLLLL.LL";
         assert!(answer.is_ok());
         assert_eq!(answer.unwrap(), 26);
     }
+
+    #[test]
+    fn declarative_rules() {
+        assert_eq!(
+            "von-neumann B0/S012".parse::<Rule>().unwrap(),
+            Rule {
+                neighbourhood: Neighbourhood::VonNeumann,
+                birth: vec![0],
+                survival: vec![0, 1, 2],
+            }
+        );
+        assert!("moore B0".parse::<Rule>().is_err());
+        assert!("hexagonal B0/S0".parse::<Rule>().is_err());
+
+        // every seat fills up, then the middle one is left since it has four neighbours
+        let rule: Rule = "von-neumann B0/S0123".parse().unwrap();
+        let airplane = Airplane::from_input(".L.\nLLL\n.L.").unwrap();
+        let mut generations = 0;
+        let (simulation, outcome) = simulate(&airplane, &rule, None, |_| generations += 1);
+        assert_eq!(simulation.to_string(), ".#.\n#L#\n.#.\n");
+        assert_eq!(outcome, Outcome::Stabilized { generation: 2 });
+        assert_eq!(generations, 3);
+    }
+
+    #[test]
+    fn precomputed_neighbours() {
+        let airplane = Airplane::from_input("L.L.L\n.....\nL.L.L").unwrap();
+        let line_of_sight = Neighbours::new(&airplane, Neighbourhood::LineOfSight);
+        assert_eq!(line_of_sight.of(2), &[4, 0, 12, 14, 10]);
+        assert!(line_of_sight.of(1).is_empty());
+        // nothing but floor is right next to it
+        assert!(Neighbours::new(&airplane, Neighbourhood::Moore)
+            .of(2)
+            .is_empty());
+    }
+
+    #[test]
+    fn oscillation_and_limits() {
+        // every seat fills, then all of them have a neighbour so they all empty again
+        let rule: Rule = "von-neumann B0/S0".parse().unwrap();
+        let airplane = Airplane::from_input("LLL").unwrap();
+        let (simulation, outcome) = simulate(&airplane, &rule, None, |_| {});
+        assert_eq!(
+            outcome,
+            Outcome::Oscillating {
+                start: 0,
+                period: 2
+            }
+        );
+        assert_eq!(simulation.to_string(), "LLL\n");
+
+        let (simulation, outcome) = simulate(&airplane, &rule, Some(1), |_| {});
+        assert_eq!(outcome, Outcome::LimitReached { generations: 1 });
+        assert_eq!(simulation.to_string(), "###\n");
+        assert!(crate::settle(&airplane, &rule, |_| {}).is_err());
+    }
+
+    #[test]
+    fn frames() {
+        let rule: Rule = Rule::PART1.parse().unwrap();
+        let airplane = Airplane::from_input("L.\nLL").unwrap();
+        let mut text: Vec<u8> = Vec::new();
+        let mut images: Vec<Vec<u8>> = Vec::new();
+        simulate(&airplane, &rule, None, |simulation| {
+            write_text_frame(simulation, &mut text).unwrap();
+            let mut image = Vec::new();
+            write_ppm(simulation, 2, &mut image).unwrap();
+            images.push(image);
+        });
+        assert_eq!(
+            String::from_utf8(text).unwrap(),
+            "generation 0\nL.\nLL\n\ngeneration 1\n#.\n##\n\n"
+        );
+
+        let header = b"P6\n4 4\n255\n";
+        assert!(images.iter().all(|image| image.starts_with(header)));
+        assert_eq!(images[0].len(), header.len() + 4 * 4 * 3);
+        // the top left pixel of the first seat
+        assert_eq!(images[0][header.len()..header.len() + 3], EMPTY_COLOUR);
+        assert_eq!(images[1][header.len()..header.len() + 3], OCCUPIED_COLOUR);
+    }
 }
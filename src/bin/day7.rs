@@ -1,11 +1,11 @@
 #![feature(iterator_fold_self)]
 
-use advent_of_code_2020::UnsolvedError;
-use std::collections::{HashSet, VecDeque};
+use advent_of_code_2020::{AdventOfCodeError, UnsolvedError};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::error::Error;
-use std::iter::FromIterator;
+use std::fmt;
+use std::fmt::Formatter;
 use std::ops::Deref;
-use std::str::Lines;
 
 #[derive(Debug, PartialEq)]
 struct Quantity {
@@ -19,126 +19,915 @@ struct Rule {
     requirements: Vec<Quantity>,
 }
 
-fn normalize_bag_name(name: &str) -> &str {
-    return name.strip_suffix("s").unwrap_or(name);
+// "2 Muted  Yellow bags." and "muted yellow bag" both normalize to "muted yellow bag"
+fn normalize_bag_name(name: &str) -> String {
+    let mut words: Vec<String> = name
+        .trim_end_matches('.')
+        .split_whitespace()
+        .map(|word| word.to_lowercase())
+        .collect();
+    if words
+        .first()
+        .is_some_and(|word| word.parse::<u32>().is_ok())
+    {
+        words.remove(0);
+    }
+    if let Some("bag") | Some("bags") = words.last().map(|word| word.as_str()) {
+        words.pop();
+    }
+    words.push("bag".to_string());
+    words.join(" ")
 }
 
 impl Rule {
-    pub fn from_line(line: &str) -> Result<Rule, UnsolvedError> {
-        // first thing is remove the trailing period to make this easier
-        let parts: Vec<&str> = line
-            .strip_suffix(".")
-            .map(|l| l.split(" contain ").collect())
-            .ok_or(UnsolvedError)?;
-
-        let name = *parts.get(0).ok_or(UnsolvedError)?;
-        let remainder = *parts.get(1).ok_or(UnsolvedError)?;
-        let requirements: Vec<Quantity> = match remainder {
-            "no other bags" => Ok(Vec::new()),
-            _ => remainder
-                .split(", ")
-                .map(|r| {
-                    let quantity: Vec<&str> = r.splitn(2, " ").collect();
-                    return Ok(Quantity {
-                        bag: String::from(normalize_bag_name(
-                            *quantity.get(1).ok_or(UnsolvedError)?,
-                        )),
-                        amount: quantity
-                            .get(0)
-                            .and_then(|q| q.parse::<u32>().ok())
-                            .ok_or(UnsolvedError)?,
-                    });
-                })
-                .collect(),
-        }?;
-        Ok(Rule {
-            bag: String::from(normalize_bag_name(name)), // normalize the name by removing the trailing s
-            requirements,
+    fn parse(line: &str, line_number: usize) -> Result<Rule, ParseError> {
+        let mut parser = RuleParser::new(line, line_number)?;
+        parser.rule()
+    }
+}
+
+// strip the "bag" suffix we normalize every name to so we can pick singular or plural
+fn bag_description(bag: &str) -> &str {
+    bag.strip_suffix(" bag").unwrap_or(bag)
+}
+
+// prints the canonical form of a rule, which parses back to the same rule
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} bags contain ", bag_description(&self.bag))?;
+        if self.requirements.is_empty() {
+            return write!(f, "no other bags.");
+        }
+        for (i, requirement) in self.requirements.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            let noun = if requirement.amount == 1 {
+                "bag"
+            } else {
+                "bags"
+            };
+            write!(
+                f,
+                "{} {} {}",
+                requirement.amount,
+                bag_description(&requirement.bag),
+                noun
+            )?;
+        }
+        write!(f, ".")
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+struct ParseError {
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+impl Error for ParseError {}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Token<'a> {
+    Word(&'a str),
+    Number(u32),
+    Comma,
+    Period,
+}
+
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "'{}'", word),
+            Token::Number(number) => write!(f, "'{}'", number),
+            Token::Comma => write!(f, "','"),
+            Token::Period => write!(f, "'.'"),
+        }
+    }
+}
+
+/// A recursive descent parser for a single rule:
+///
+/// rule     := name "contain" contents "."
+/// contents := "no" "other" bag | quantity ("," quantity)*
+/// quantity := number name
+/// name     := word+ bag
+/// bag      := "bag" | "bags"
+///
+/// Any amount of whitespace may separate tokens and keywords are case insensitive.
+struct RuleParser<'a> {
+    // (column, token); columns are 1-based character offsets
+    tokens: Vec<(usize, Token<'a>)>,
+    position: usize,
+    line: usize,
+    end_column: usize,
+}
+
+impl<'a> RuleParser<'a> {
+    fn new(input: &'a str, line: usize) -> Result<RuleParser<'a>, ParseError> {
+        let mut tokens = Vec::new();
+        let mut chars = input.char_indices().enumerate().peekable();
+        while let Some((column, (start, c))) = chars.next() {
+            let column = column + 1;
+            match c {
+                ',' => tokens.push((column, Token::Comma)),
+                '.' => tokens.push((column, Token::Period)),
+                c if c.is_whitespace() => {}
+                _ => {
+                    let mut end = start + c.len_utf8();
+                    while let Some(&(_, (next_start, next))) = chars.peek() {
+                        if next.is_whitespace() || next == ',' || next == '.' {
+                            break;
+                        }
+                        end = next_start + next.len_utf8();
+                        chars.next();
+                    }
+                    let word = &input[start..end];
+                    if word.chars().all(|c| c.is_ascii_digit()) {
+                        let number = word.parse::<u32>().map_err(|_| ParseError {
+                            line,
+                            column,
+                            message: format!("amount '{}' is too large", word),
+                        })?;
+                        tokens.push((column, Token::Number(number)));
+                    } else {
+                        tokens.push((column, Token::Word(word)));
+                    }
+                }
+            }
+        }
+        Ok(RuleParser {
+            tokens,
+            position: 0,
+            line,
+            end_column: input.chars().count() + 1,
         })
     }
+
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.tokens.get(self.position).map(|(_, token)| token)
+    }
+
+    fn error(&self, expected: &str) -> ParseError {
+        let (column, found) = match self.tokens.get(self.position) {
+            Some((column, token)) => (*column, token.to_string()),
+            None => (self.end_column, "end of line".to_string()),
+        };
+        ParseError {
+            line: self.line,
+            column,
+            message: format!("expected {} but found {}", expected, found),
+        }
+    }
+
+    fn is_keyword(token: Option<&Token>, keyword: &str) -> bool {
+        match token {
+            Some(Token::Word(word)) => word.eq_ignore_ascii_case(keyword),
+            _ => false,
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<(), ParseError> {
+        if !RuleParser::is_keyword(self.peek(), keyword) {
+            return Err(self.error(&format!("'{}'", keyword)));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn rule(&mut self) -> Result<Rule, ParseError> {
+        let bag = self.name()?;
+        self.keyword("contain")?;
+        let requirements = self.contents()?;
+        if self.peek() != Some(&Token::Period) {
+            return Err(self.error("'.' at the end of the rule"));
+        }
+        self.position += 1;
+        if self.peek().is_some() {
+            return Err(self.error("end of line"));
+        }
+        Ok(Rule { bag, requirements })
+    }
+
+    fn contents(&mut self) -> Result<Vec<Quantity>, ParseError> {
+        if RuleParser::is_keyword(self.peek(), "no") {
+            self.position += 1;
+            self.keyword("other")?;
+            self.bag()?;
+            return Ok(Vec::new());
+        }
+
+        let mut requirements = vec![self.quantity()?];
+        while self.peek() == Some(&Token::Comma) {
+            self.position += 1;
+            requirements.push(self.quantity()?);
+        }
+        Ok(requirements)
+    }
+
+    fn quantity(&mut self) -> Result<Quantity, ParseError> {
+        let amount = match self.peek() {
+            Some(Token::Number(amount)) => *amount,
+            _ => return Err(self.error("an amount")),
+        };
+        self.position += 1;
+        let bag = self.name()?;
+        Ok(Quantity { bag, amount })
+    }
+
+    // a name is every word up to the "bag" or "bags" that ends it, so names may end in "s"
+    fn name(&mut self) -> Result<String, ParseError> {
+        let mut words: Vec<&str> = Vec::new();
+        while let Some(Token::Word(word)) = self.peek() {
+            if RuleParser::is_keyword(self.peek(), "bag")
+                || RuleParser::is_keyword(self.peek(), "bags")
+            {
+                break;
+            }
+            words.push(word);
+            self.position += 1;
+        }
+        if words.is_empty() {
+            return Err(self.error("a bag name"));
+        }
+        self.bag()?;
+        Ok(format!("{} bag", words.join(" ").to_lowercase()))
+    }
+
+    fn bag(&mut self) -> Result<(), ParseError> {
+        if RuleParser::is_keyword(self.peek(), "bag") || RuleParser::is_keyword(self.peek(), "bags")
+        {
+            self.position += 1;
+            return Ok(());
+        }
+        Err(self.error("'bag' or 'bags'"))
+    }
 }
 
-fn part1(input: &str) -> Result<(), Box<dyn Error>> {
-    // this is a shitty graph
-    let rules: Vec<Rule> = input
-        .lines()
-        .map(|line| Rule::from_line(line))
-        .collect::<Result<Vec<Rule>, UnsolvedError>>()?;
-
-    let starting_node = "shiny gold bag";
-    let mut queue: VecDeque<&str> = VecDeque::new();
-    queue.push_back(starting_node);
-    // we walk the graph finding all nodes
-    let mut seen: HashSet<&str> = HashSet::new();
-    while !queue.is_empty() {
-        // this cannot fail
-        let current = queue.pop_front().ok_or(UnsolvedError)?;
-
-        // find all my neighbors
-        let neighbors: Vec<&Rule> = rules
-            .iter()
-            .filter(|rule| {
-                rule.requirements
-                    .iter()
-                    .find(|requirement| requirement.bag == current)
-                    .is_some()
+/// An indexed view of the rules where every bag name is interned to a numeric id.
+/// Both directions of the containment relation are kept so that walking either way
+/// never has to rescan the rule list.
+#[derive(Debug, PartialEq)]
+struct BagGraph {
+    names: Vec<String>,
+    ids: HashMap<String, usize>,
+    // bag -> (inner bag, amount)
+    contains: Vec<Vec<(usize, u32)>>,
+    // bag -> (outer bag, amount)
+    contained_by: Vec<Vec<(usize, u32)>>,
+    // how many rules define each bag; anything other than one is a problem
+    definitions: Vec<u32>,
+}
+
+impl BagGraph {
+    fn from_rules(rules: &[Rule]) -> BagGraph {
+        let mut graph = BagGraph {
+            names: Vec::new(),
+            ids: HashMap::new(),
+            contains: Vec::new(),
+            contained_by: Vec::new(),
+            definitions: Vec::new(),
+        };
+
+        for rule in rules {
+            let outer = graph.intern(&rule.bag);
+            graph.definitions[outer] += 1;
+            for requirement in &rule.requirements {
+                let inner = graph.intern(&requirement.bag);
+                graph.contains[outer].push((inner, requirement.amount));
+                graph.contained_by[inner].push((outer, requirement.amount));
+            }
+        }
+
+        graph
+    }
+
+    fn intern(&mut self, bag: &str) -> usize {
+        if let Some(id) = self.ids.get(bag) {
+            return *id;
+        }
+        let id = self.names.len();
+        self.names.push(bag.to_string());
+        self.ids.insert(bag.to_string(), id);
+        self.contains.push(Vec::new());
+        self.contained_by.push(Vec::new());
+        self.definitions.push(0);
+        id
+    }
+
+    fn id(&self, bag: &str) -> Result<usize, UnsolvedError> {
+        self.ids
+            .get(&normalize_bag_name(bag))
+            .copied()
+            .ok_or(UnsolvedError)
+    }
+
+    fn name(&self, id: usize) -> &str {
+        self.names[id].deref()
+    }
+
+    /// Total number of bags required inside the given bag, an error if that many can't
+    /// be counted.
+    fn count_inside(&self, bag: &str) -> Result<u64, AdventOfCodeError> {
+        let id = self
+            .id(bag)
+            .map_err(|_| AdventOfCodeError::Custom(format!("Unknown bag: {}", bag)))?;
+        let mut memo: Vec<Option<u64>> = vec![None; self.names.len()];
+        self.count_inside_memoized(id, &mut memo).ok_or_else(|| {
+            AdventOfCodeError::Custom(format!("{} holds more than {} bags", bag, u64::MAX))
+        })
+    }
+
+    // None once the count overflows
+    fn count_inside_memoized(&self, id: usize, memo: &mut Vec<Option<u64>>) -> Option<u64> {
+        if let Some(count) = memo[id] {
+            return Some(count);
+        }
+        let mut count: u64 = 0;
+        for &(inner, amount) in &self.contains[id] {
+            let each = self.count_inside_memoized(inner, memo)?.checked_add(1)?;
+            count = count.checked_add(each.checked_mul(amount as u64)?)?;
+        }
+        memo[id] = Some(count);
+        Some(count)
+    }
+
+    /// Every bag that can eventually contain the given bag.
+    fn containers_of(&self, bag: &str) -> Result<Vec<&str>, UnsolvedError> {
+        let id = self.id(bag)?;
+        Ok(self
+            .reachable(id, &self.contained_by)
+            .into_iter()
+            .map(|outer| self.name(outer))
+            .collect())
+    }
+
+    /// How many of every bag type end up inside the given bag, ordered by name.
+    /// The graph must be acyclic.
+    fn bill_of_materials(&self, bag: &str) -> Result<Vec<Material<'_>>, AdventOfCodeError> {
+        let id = self
+            .id(bag)
+            .map_err(|_| AdventOfCodeError::Custom(format!("Unknown bag: {}", bag)))?;
+
+        // reverse post-order of the reachable subgraph is a topological order,
+        // so every bag's total is final before it is pushed down to its contents
+        let mut visited = vec![false; self.names.len()];
+        let mut order = Vec::new();
+        self.post_order(id, &mut visited, &mut order);
+
+        let mut totals = vec![0u64; self.names.len()];
+        totals[id] = 1;
+        for &current in order.iter().rev() {
+            for &(inner, amount) in &self.contains[current] {
+                totals[inner] = totals[current]
+                    .checked_mul(amount as u64)
+                    .and_then(|added| totals[inner].checked_add(added))
+                    .ok_or_else(|| {
+                        AdventOfCodeError::Custom(format!(
+                            "{} holds more than {} {}s",
+                            bag,
+                            u64::MAX,
+                            self.name(inner)
+                        ))
+                    })?;
+            }
+        }
+
+        let mut materials: Vec<Material> = order
+            .into_iter()
+            .filter(|&current| current != id)
+            .map(|current| Material {
+                bag: self.name(current),
+                amount: totals[current],
+                leaf: self.contains[current].is_empty(),
             })
             .collect();
+        materials.sort_by(|a, b| a.bag.cmp(b.bag));
+        Ok(materials)
+    }
 
-        for neighbor in neighbors {
-            let bag = neighbor.bag.deref();
-            seen.insert(bag);
-            queue.push_back(bag);
+    fn post_order(&self, id: usize, visited: &mut Vec<bool>, order: &mut Vec<usize>) {
+        visited[id] = true;
+        for &(inner, _) in &self.contains[id] {
+            if !visited[inner] {
+                self.post_order(inner, visited, order);
+            }
         }
+        order.push(id);
     }
 
-    println!("part1: {}", seen.len());
-    Ok(())
-}
+    /// The longest chain of bags nested inside the given bag; an empty bag has depth 0.
+    fn max_depth(&self, bag: &str) -> Result<u32, UnsolvedError> {
+        let id = self.id(bag)?;
+        let mut memo: Vec<Option<u32>> = vec![None; self.names.len()];
+        Ok(self.max_depth_memoized(id, &mut memo))
+    }
 
-fn part2(input: &str) -> Result<u32, Box<dyn Error>> {
-    // this is a shitty graph
-    let rules: Vec<Rule> = input
-        .lines()
-        .map(|line| Rule::from_line(line))
-        .collect::<Result<Vec<Rule>, UnsolvedError>>()?;
-
-    let starting_node = "shiny gold bag";
-    let mut queue: VecDeque<&str> = VecDeque::new();
-    queue.push_back(starting_node);
-    // we walk the graph finding all nodes
-    let mut result = 0;
-    while !queue.is_empty() {
-        let current = queue.pop_front().ok_or(UnsolvedError)?;
-        let current_rule = rules
+    fn max_depth_memoized(&self, id: usize, memo: &mut Vec<Option<u32>>) -> u32 {
+        if let Some(depth) = memo[id] {
+            return depth;
+        }
+        let depth = self.contains[id]
             .iter()
-            .find(|r| r.bag == current)
-            .ok_or(UnsolvedError)?;
-        for requirement in &current_rule.requirements {
-            result += requirement.amount;
-            for i in 0..requirement.amount {
-                queue.push_back(requirement.bag.deref());
+            .map(|&(inner, _)| 1 + self.max_depth_memoized(inner, memo))
+            .max()
+            .unwrap_or(0);
+        memo[id] = Some(depth);
+        depth
+    }
+
+    /// Every chain of containment leading from the outer bag down to the inner bag.
+    fn paths(&self, outer: &str, inner: &str) -> Result<Vec<Vec<&str>>, UnsolvedError> {
+        let from = self.id(outer)?;
+        let to = self.id(inner)?;
+
+        // prune the search to bags that can actually hold the target
+        let mut useful = vec![false; self.names.len()];
+        useful[to] = true;
+        for id in self.reachable(to, &self.contained_by) {
+            useful[id] = true;
+        }
+
+        let mut paths = Vec::new();
+        if useful[from] {
+            let mut path = vec![from];
+            self.collect_paths(to, &useful, &mut path, &mut paths);
+        }
+        Ok(paths)
+    }
+
+    fn collect_paths<'g>(
+        &'g self,
+        to: usize,
+        useful: &[bool],
+        path: &mut Vec<usize>,
+        paths: &mut Vec<Vec<&'g str>>,
+    ) {
+        let current = *path.last().unwrap();
+        if current == to && path.len() > 1 {
+            paths.push(path.iter().map(|&id| self.name(id)).collect());
+            return;
+        }
+        for &(next, _) in &self.contains[current] {
+            if !useful[next] {
+                continue;
+            }
+            path.push(next);
+            self.collect_paths(to, useful, path, paths);
+            path.pop();
+        }
+    }
+
+    // breadth first walk along the given adjacency, in visiting order, excluding the start
+    fn reachable(&self, id: usize, adjacency: &[Vec<(usize, u32)>]) -> Vec<usize> {
+        let mut seen = vec![false; self.names.len()];
+        let mut queue: VecDeque<usize> = VecDeque::new();
+        queue.push_back(id);
+        let mut reached = Vec::new();
+        while let Some(current) = queue.pop_front() {
+            for &(next, _) in &adjacency[current] {
+                if seen[next] {
+                    continue;
+                }
+                seen[next] = true;
+                reached.push(next);
+                queue.push_back(next);
             }
         }
+        reached
     }
 
+    // which bags take part in an export
+    fn included(&self, filter: &ExportFilter) -> Result<Vec<bool>, UnsolvedError> {
+        let (start, adjacency) = match filter {
+            ExportFilter::All => return Ok(vec![true; self.names.len()]),
+            ExportFilter::ReachableFrom(bag) => (self.id(bag)?, &self.contains),
+            ExportFilter::ReachableTo(bag) => (self.id(bag)?, &self.contained_by),
+        };
+        let mut included = vec![false; self.names.len()];
+        included[start] = true;
+        for id in self.reachable(start, adjacency) {
+            included[id] = true;
+        }
+        Ok(included)
+    }
+
+    // a bag is shared when more than one distinct bag holds it directly
+    fn is_shared(&self, id: usize) -> bool {
+        self.contained_by[id]
+            .iter()
+            .map(|&(outer, _)| outer)
+            .collect::<HashSet<usize>>()
+            .len()
+            > 1
+    }
+
+    /// Renders the containment graph in GraphViz DOT with amounts as edge labels.
+    fn to_dot(&self, options: &ExportOptions) -> Result<String, UnsolvedError> {
+        let included = self.included(&options.filter)?;
+        let mut dot = String::from("digraph bags {\n");
+        for id in (0..self.names.len()).filter(|&id| included[id]) {
+            if options.highlight_shared && self.is_shared(id) {
+                dot += &format!(
+                    "    \"{}\" [style=filled, fillcolor=gold];\n",
+                    self.name(id)
+                );
+            } else {
+                dot += &format!("    \"{}\";\n", self.name(id));
+            }
+        }
+        for id in (0..self.names.len()).filter(|&id| included[id]) {
+            for &(inner, amount) in self.contains[id].iter().filter(|(i, _)| included[*i]) {
+                dot += &format!(
+                    "    \"{}\" -> \"{}\" [label=\"{}\"];\n",
+                    self.name(id),
+                    self.name(inner),
+                    amount
+                );
+            }
+        }
+        dot += "}\n";
+        Ok(dot)
+    }
+
+    /// Renders the containment graph as a Mermaid flowchart with amounts as edge labels.
+    fn to_mermaid(&self, options: &ExportOptions) -> Result<String, UnsolvedError> {
+        let included = self.included(&options.filter)?;
+        // mermaid node ids can't contain spaces so use the interned ids instead
+        let mut mermaid = String::from("graph LR\n");
+        for id in (0..self.names.len()).filter(|&id| included[id]) {
+            mermaid += &format!("    n{}[\"{}\"]\n", id, self.name(id));
+        }
+        for id in (0..self.names.len()).filter(|&id| included[id]) {
+            for &(inner, amount) in self.contains[id].iter().filter(|(i, _)| included[*i]) {
+                mermaid += &format!("    n{} -->|{}| n{}\n", id, amount, inner);
+            }
+        }
+        if options.highlight_shared {
+            let shared: Vec<String> = (0..self.names.len())
+                .filter(|&id| included[id] && self.is_shared(id))
+                .map(|id| format!("n{}", id))
+                .collect();
+            if !shared.is_empty() {
+                mermaid += "    classDef shared fill:#ffd700\n";
+                mermaid += &format!("    class {} shared\n", shared.join(","));
+            }
+        }
+        Ok(mermaid)
+    }
+
+    /// Checks the rules for anything that would make the solvers misbehave.
+    fn validate(&self) -> Validation {
+        let mut validation = Validation::default();
+
+        for id in 0..self.names.len() {
+            match self.definitions[id] {
+                0 => {
+                    for &(outer, _) in &self.contained_by[id] {
+                        validation
+                            .undefined
+                            .push((self.name(id).to_string(), self.name(outer).to_string()));
+                    }
+                }
+                1 => {}
+                _ => validation.duplicates.push(self.name(id).to_string()),
+            }
+            if self.contained_by[id].is_empty() {
+                validation.unreferenced.push(self.name(id).to_string());
+            }
+        }
+
+        // 0 = unvisited, 1 = on the current path, 2 = finished
+        let mut state = vec![0u8; self.names.len()];
+        let mut path: Vec<usize> = Vec::new();
+        for id in 0..self.names.len() {
+            if state[id] == 0 {
+                self.find_cycles(id, &mut state, &mut path, &mut validation.cycles);
+            }
+        }
+
+        validation
+    }
+
+    fn find_cycles(
+        &self,
+        id: usize,
+        state: &mut Vec<u8>,
+        path: &mut Vec<usize>,
+        cycles: &mut Vec<Vec<String>>,
+    ) {
+        state[id] = 1;
+        path.push(id);
+        for &(inner, _) in &self.contains[id] {
+            match state[inner] {
+                0 => self.find_cycles(inner, state, path, cycles),
+                1 => {
+                    // found a back edge; the cycle is the tail of the path starting at inner
+                    let start = path.iter().position(|&p| p == inner).unwrap();
+                    let mut cycle: Vec<String> = path[start..]
+                        .iter()
+                        .map(|&p| self.name(p).to_string())
+                        .collect();
+                    cycle.push(self.name(inner).to_string());
+                    cycles.push(cycle);
+                }
+                _ => {}
+            }
+        }
+        path.pop();
+        state[id] = 2;
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Material<'g> {
+    bag: &'g str,
+    amount: u64,
+    // leaf bags contain nothing else; the rest are intermediate
+    leaf: bool,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum ExportFilter {
+    All,
+    // the bag and everything it (transitively) contains
+    ReachableFrom(String),
+    // the bag and everything that can (transitively) contain it
+    ReachableTo(String),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+struct ExportOptions {
+    filter: ExportFilter,
+    highlight_shared: bool,
+}
+
+impl ExportOptions {
+    fn new() -> ExportOptions {
+        ExportOptions {
+            filter: ExportFilter::All,
+            highlight_shared: false,
+        }
+    }
+}
+
+/// The outcome of validating a rule set. Unreferenced bags are only informational
+/// since every rule set has at least one outermost bag.
+#[derive(Debug, PartialEq, Default)]
+struct Validation {
+    cycles: Vec<Vec<String>>,
+    // (missing bag, bag whose rule references it)
+    undefined: Vec<(String, String)>,
+    duplicates: Vec<String>,
+    unreferenced: Vec<String>,
+}
+
+impl Validation {
+    fn is_valid(&self) -> bool {
+        self.cycles.is_empty() && self.undefined.is_empty() && self.duplicates.is_empty()
+    }
+}
+
+impl fmt::Display for Validation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for cycle in &self.cycles {
+            writeln!(f, "containment cycle: {}", cycle.join(" -> "))?;
+        }
+        for (bag, referenced_by) in &self.undefined {
+            writeln!(
+                f,
+                "undefined bag: {} (referenced by {})",
+                bag, referenced_by
+            )?;
+        }
+        for bag in &self.duplicates {
+            writeln!(f, "duplicate rule: {}", bag)?;
+        }
+        for bag in &self.unreferenced {
+            writeln!(f, "unreferenced bag: {}", bag)?;
+        }
+        Ok(())
+    }
+}
+
+// blank lines are skipped but still counted so errors point at the right line
+fn parse_rules(input: &str) -> Result<Vec<Rule>, ParseError> {
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| Rule::parse(line, i + 1))
+        .collect::<Result<Vec<Rule>, ParseError>>()
+}
+
+// refuse to hand back a graph the solvers could loop on or silently miscount
+fn load_graph(input: &str) -> Result<BagGraph, AdventOfCodeError> {
+    let rules = parse_rules(input)
+        .map_err(|err| AdventOfCodeError::Custom(format!("Could not parse rules: {}", err)))?;
+    let graph = BagGraph::from_rules(&rules);
+    let validation = graph.validate();
+    if !validation.is_valid() {
+        return Err(AdventOfCodeError::Custom(format!(
+            "Invalid rules\n{}",
+            validation
+        )));
+    }
+    Ok(graph)
+}
+
+fn part1(input: &str, bag: &str) -> Result<usize, Box<dyn Error>> {
+    let graph = load_graph(input)?;
+
+    let answer = graph.containers_of(bag)?.len();
+
+    println!("part1: {}", answer);
+    Ok(answer)
+}
+
+fn part2(input: &str, bag: &str) -> Result<u64, Box<dyn Error>> {
+    let graph = load_graph(input)?;
+
+    let result = graph.count_inside(bag)?;
+
     println!("part2: {}", result);
 
     Ok(result)
 }
 
+// usage: day7 dot|mermaid [from <bag>|to <bag>] [shared]
+fn export(input: &str, args: &[String]) -> Result<String, Box<dyn Error>> {
+    let graph = load_graph(input)?;
+    let mut options = ExportOptions::new();
+    let mut rest = args.iter().skip(1);
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "from" | "to" => {
+                let bag = rest.next().ok_or(AdventOfCodeError::Custom(format!(
+                    "Missing bag name after {}",
+                    arg
+                )))?;
+                let bag = normalize_bag_name(bag);
+                options.filter = match arg.as_str() {
+                    "from" => ExportFilter::ReachableFrom(bag),
+                    _ => ExportFilter::ReachableTo(bag),
+                };
+            }
+            "shared" => options.highlight_shared = true,
+            unknown => {
+                return Err(Box::new(AdventOfCodeError::Custom(format!(
+                    "Unknown export option: {}",
+                    unknown
+                ))))
+            }
+        }
+    }
+
+    let rendered = match args.first().map(|format| format.as_str()) {
+        Some("dot") => graph.to_dot(&options)?,
+        Some("mermaid") => graph.to_mermaid(&options)?,
+        _ => {
+            return Err(Box::new(AdventOfCodeError::Custom(
+                "Expected an export format of dot or mermaid".to_string(),
+            )))
+        }
+    };
+    Ok(rendered)
+}
+
+// usage: day7 query <bag> [<inner bag>]
+fn query(input: &str, args: &[String]) -> Result<String, Box<dyn Error>> {
+    let graph = load_graph(input)?;
+    let bag = args.get(1).ok_or(AdventOfCodeError::Custom(
+        "Missing bag name to query".to_string(),
+    ))?;
+    let unknown = |name: &str| AdventOfCodeError::Custom(format!("Unknown bag: {}", name));
+
+    let mut report = String::new();
+    for material in graph.bill_of_materials(bag)? {
+        let kind = if material.leaf {
+            "leaf"
+        } else {
+            "intermediate"
+        };
+        report += &format!("{} x {} ({})\n", material.amount, material.bag, kind);
+    }
+    report += &format!("total: {}\n", graph.count_inside(bag)?);
+    report += &format!("max depth: {}\n", graph.max_depth(bag)?);
+
+    if let Some(inner) = args.get(2) {
+        for path in graph.paths(bag, inner).map_err(|_| unknown(inner))? {
+            report += &format!("path: {}\n", path.join(" -> "));
+        }
+    }
+    Ok(report)
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     let input = include_str!("day7_1.txt");
-    part1(input)?;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(|command| command.as_str()) {
+        None => {}
+        Some("query") => {
+            print!("{}", query(input, &args)?);
+            return Ok(());
+        }
+        Some("dot") | Some("mermaid") => {
+            print!("{}", export(input, &args)?);
+            return Ok(());
+        }
+        Some(other) => {
+            return Err(Box::new(AdventOfCodeError::Custom(format!(
+                "unknown command {}, usage: day7 [query <bag> [<inner bag>] | \
+                 dot|mermaid [from <bag>|to <bag>] [shared]]",
+                other
+            ))))
+        }
+    }
 
-    part2(input)?;
+    let bag = "shiny gold bag";
+    part1(input, bag)?;
+
+    part2(input, bag)?;
 
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::part2;
+    use crate::{
+        normalize_bag_name, parse_rules, part1, part2, BagGraph, ExportFilter, ExportOptions,
+        Material, ParseError, Quantity, Rule,
+    };
+
+    const SAMPLE: &str = "light red bags contain 1 bright white bag, 2 muted yellow bags.
+dark orange bags contain 3 bright white bags, 4 muted yellow bags.
+bright white bags contain 1 shiny gold bag.
+muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.
+shiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.
+dark olive bags contain 3 faded blue bags, 4 dotted black bags.
+vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
+faded blue bags contain no other bags.
+dotted black bags contain no other bags.";
+
+    #[test]
+    fn part1_test() {
+        let answer = part1(SAMPLE, "shiny gold bag");
+        assert!(answer.is_ok());
+        assert_eq!(answer.unwrap(), 4);
+    }
+
+    #[test]
+    fn graph_counts_test() {
+        let rules = parse_rules(SAMPLE).unwrap();
+        let graph = BagGraph::from_rules(&rules);
+        assert_eq!(graph.count_inside("shiny gold bag").unwrap(), 32);
+        assert_eq!(graph.count_inside("faded blue bag").unwrap(), 0);
+        let mut containers = graph.containers_of("muted yellow bag").unwrap();
+        containers.sort();
+        assert_eq!(containers, vec!["dark orange bag", "light red bag"]);
+        assert!(graph.count_inside("missing bag").is_err());
+    }
+
+    #[test]
+    fn count_overflow_test() {
+        // ten levels of 99 bags each is more than a u64 can count
+        let shades = "abcdefghijk";
+        let mut rules: String = shades
+            .chars()
+            .zip(shades.chars().skip(1))
+            .map(|(outer, inner)| {
+                format!("shade {} bags contain 99 shade {} bags.\n", outer, inner)
+            })
+            .collect();
+        rules += "shade k bags contain no other bags.";
+        let graph = BagGraph::from_rules(&parse_rules(&rules).unwrap());
+        assert_eq!(
+            graph.count_inside("shade b bag").unwrap(),
+            922838852049800499
+        );
+        assert_eq!(
+            graph.count_inside("shade a bag").unwrap_err().to_string(),
+            "Error: shade a bag holds more than 18446744073709551615 bags"
+        );
+        assert_eq!(
+            graph
+                .bill_of_materials("shade a bag")
+                .unwrap_err()
+                .to_string(),
+            "Error: shade a bag holds more than 18446744073709551615 shade k bags"
+        );
+    }
 
     #[test]
     fn part2_test() {
@@ -149,8 +938,233 @@ dark yellow bags contain 2 dark green bags.
 dark green bags contain 2 dark blue bags.
 dark blue bags contain 2 dark violet bags.
 dark violet bags contain no other bags.";
-        let answer = part2(sample);
+        let answer = part2(sample, "shiny gold bag");
         assert!(answer.is_ok());
         assert_eq!(answer.unwrap(), 126);
     }
+
+    #[test]
+    fn validate_test() {
+        let sample = "shiny gold bags contain 2 dark red bags.
+dark red bags contain 1 dark blue bag, 3 pale lime bags.
+dark blue bags contain 1 shiny gold bag.
+dark blue bags contain no other bags.";
+        let rules = parse_rules(sample).unwrap();
+        let validation = BagGraph::from_rules(&rules).validate();
+        assert!(!validation.is_valid());
+        assert_eq!(
+            validation.cycles,
+            vec![vec![
+                "shiny gold bag",
+                "dark red bag",
+                "dark blue bag",
+                "shiny gold bag"
+            ]]
+        );
+        assert_eq!(
+            validation.undefined,
+            vec![("pale lime bag".to_string(), "dark red bag".to_string())]
+        );
+        assert_eq!(validation.duplicates, vec!["dark blue bag"]);
+        assert!(validation.unreferenced.is_empty());
+        assert!(part2(sample, "shiny gold bag").is_err());
+
+        let rules = parse_rules(SAMPLE).unwrap();
+        let validation = BagGraph::from_rules(&rules).validate();
+        assert!(validation.is_valid());
+        assert_eq!(
+            validation.unreferenced,
+            vec!["light red bag", "dark orange bag"]
+        );
+    }
+
+    #[test]
+    fn export_test() {
+        let rules = parse_rules(SAMPLE).unwrap();
+        let graph = BagGraph::from_rules(&rules);
+        let options = ExportOptions {
+            filter: ExportFilter::ReachableFrom("dark olive bag".to_string()),
+            highlight_shared: true,
+        };
+        assert_eq!(
+            graph.to_dot(&options).unwrap(),
+            "digraph bags {
+    \"faded blue bag\" [style=filled, fillcolor=gold];
+    \"dark olive bag\";
+    \"dotted black bag\" [style=filled, fillcolor=gold];
+    \"dark olive bag\" -> \"faded blue bag\" [label=\"3\"];
+    \"dark olive bag\" -> \"dotted black bag\" [label=\"4\"];
+}
+"
+        );
+
+        let options = ExportOptions {
+            filter: ExportFilter::ReachableTo("bright white bag".to_string()),
+            highlight_shared: false,
+        };
+        assert_eq!(
+            graph.to_mermaid(&options).unwrap(),
+            "graph LR
+    n0[\"light red bag\"]
+    n1[\"bright white bag\"]
+    n3[\"dark orange bag\"]
+    n0 -->|1| n1
+    n3 -->|3| n1
+"
+        );
+    }
+
+    #[test]
+    fn normalize_test() {
+        assert_eq!(normalize_bag_name("1 bright white bag"), "bright white bag");
+        assert_eq!(
+            normalize_bag_name("2 Muted  yellow bags."),
+            "muted yellow bag"
+        );
+        assert_eq!(normalize_bag_name("shiny gold"), "shiny gold bag");
+    }
+
+    #[test]
+    fn query_test() {
+        let rules = parse_rules(SAMPLE).unwrap();
+        let graph = BagGraph::from_rules(&rules);
+        assert_eq!(
+            graph.bill_of_materials("light red bags").unwrap(),
+            vec![
+                Material {
+                    bag: "bright white bag",
+                    amount: 1,
+                    leaf: false
+                },
+                Material {
+                    bag: "dark olive bag",
+                    amount: 5,
+                    leaf: false
+                },
+                Material {
+                    bag: "dotted black bag",
+                    amount: 80,
+                    leaf: true
+                },
+                Material {
+                    bag: "faded blue bag",
+                    amount: 83,
+                    leaf: true
+                },
+                Material {
+                    bag: "muted yellow bag",
+                    amount: 2,
+                    leaf: false
+                },
+                Material {
+                    bag: "shiny gold bag",
+                    amount: 5,
+                    leaf: false
+                },
+                Material {
+                    bag: "vibrant plum bag",
+                    amount: 10,
+                    leaf: false
+                },
+            ]
+        );
+        assert_eq!(graph.max_depth("light red").unwrap(), 4);
+        assert_eq!(graph.max_depth("faded blue bag").unwrap(), 0);
+        assert_eq!(
+            graph.paths("light red bag", "faded blue bags").unwrap(),
+            vec![
+                vec![
+                    "light red bag",
+                    "bright white bag",
+                    "shiny gold bag",
+                    "dark olive bag",
+                    "faded blue bag"
+                ],
+                vec![
+                    "light red bag",
+                    "bright white bag",
+                    "shiny gold bag",
+                    "vibrant plum bag",
+                    "faded blue bag"
+                ],
+                vec![
+                    "light red bag",
+                    "muted yellow bag",
+                    "shiny gold bag",
+                    "dark olive bag",
+                    "faded blue bag"
+                ],
+                vec![
+                    "light red bag",
+                    "muted yellow bag",
+                    "shiny gold bag",
+                    "vibrant plum bag",
+                    "faded blue bag"
+                ],
+                vec!["light red bag", "muted yellow bag", "faded blue bag"],
+            ]
+        );
+        assert!(graph
+            .paths("faded blue bag", "light red bag")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn parser_test() {
+        assert_eq!(
+            Rule::parse(
+                "dark  grass bags contain 1 shiny gold bag ,2 bright moss bags.",
+                1
+            ),
+            Ok(Rule {
+                bag: "dark grass bag".to_string(),
+                requirements: vec![
+                    Quantity {
+                        bag: "shiny gold bag".to_string(),
+                        amount: 1
+                    },
+                    Quantity {
+                        bag: "bright moss bag".to_string(),
+                        amount: 2
+                    },
+                ]
+            })
+        );
+        assert_eq!(
+            parse_rules(
+                "faded blue bags contain no other bags.\n\nshiny gold bags contain 2 dark red bags"
+            ),
+            Err(ParseError {
+                line: 3,
+                column: 40,
+                message: "expected '.' at the end of the rule but found end of line".to_string()
+            })
+        );
+        assert_eq!(
+            Rule::parse("shiny gold bags contain two dark red bags.", 1),
+            Err(ParseError {
+                line: 1,
+                column: 25,
+                message: "expected an amount but found 'two'".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn round_trip_test() {
+        for rule in parse_rules(SAMPLE).unwrap() {
+            let printed = rule.to_string();
+            assert_eq!(Rule::parse(&printed, 1), Ok(rule));
+        }
+        let rules = parse_rules(SAMPLE).unwrap();
+        assert_eq!(
+            rules[0].to_string(),
+            "light red bags contain 1 bright white bag, 2 muted yellow bags."
+        );
+        assert_eq!(
+            rules[7].to_string(),
+            "faded blue bags contain no other bags."
+        );
+    }
 }
@@ -0,0 +1,42 @@
+use advent_of_code_2020::handheld::assembler::Assembler;
+use advent_of_code_2020::handheld::bytecode::{decode, encode};
+use advent_of_code_2020::handheld::InstructionSet;
+use std::error::Error;
+
+// usage: day8_bytecode encode <program file> <bytecode file>
+//        day8_bytecode decode <bytecode file> <program file>
+// programs may use the extended instruction set and assembler labels
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.len() != 3 {
+        return Err("usage: day8_bytecode encode|decode <input> <output>".into());
+    }
+    let instruction_set = InstructionSet::extended();
+
+    match args[0].as_str() {
+        "encode" => {
+            let source = std::fs::read_to_string(&args[1])?;
+            let instructions = Assembler::new(instruction_set).assemble(&source)?;
+            let bytes = encode(&instructions);
+            std::fs::write(&args[2], &bytes)?;
+            println!(
+                "encoded {} instructions into {} bytes",
+                instructions.len(),
+                bytes.len()
+            );
+        }
+        "decode" => {
+            let bytes = std::fs::read(&args[1])?;
+            let instructions = decode(&bytes, &instruction_set)?;
+            let text: String = instructions
+                .iter()
+                .map(|instruction| format!("{}\n", instruction))
+                .collect();
+            std::fs::write(&args[2], text)?;
+            println!("decoded {} instructions", instructions.len());
+        }
+        other => return Err(format!("unknown command {}", other).into()),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,46 @@
+use advent_of_code_2020::handheld::debugger::{Command, Debugger};
+use advent_of_code_2020::handheld::VirtualMachine;
+use std::error::Error;
+use std::io::{BufRead, Write};
+
+// usage: day8_debugger [program file]
+// without a file the puzzle input is debugged
+fn main() -> Result<(), Box<dyn Error>> {
+    let program = match std::env::args().nth(1) {
+        Some(path) => std::fs::read_to_string(path)?,
+        None => include_str!("day8_1.txt").to_string(),
+    };
+
+    let mut debugger = Debugger::new(VirtualMachine::new(&program)?);
+    println!("type help for a list of commands");
+    print!("{}", debugger.listing());
+
+    let stdin = std::io::stdin();
+    loop {
+        print!("(day8) ");
+        std::io::stdout().flush()?;
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            break;
+        }
+
+        let command = match Command::from_line(&line) {
+            Ok(command) => command,
+            Err(err) => {
+                println!("{}", err);
+                continue;
+            }
+        };
+        if command == Command::Quit {
+            break;
+        }
+
+        match debugger.execute(&command) {
+            Ok(output) => print!("{}", output),
+            Err(err) => println!("{}", err),
+        }
+    }
+
+    Ok(())
+}
@@ -1,100 +1,341 @@
 #![feature(iterator_fold_self)]
 
-use advent_of_code_2020::UnsolvedError;
-use itertools::Itertools;
-use std::collections::VecDeque;
+use advent_of_code_2020::{AdventOfCodeError, UnsolvedError};
+use itertools::process_results;
+use std::collections::{HashMap, VecDeque};
 use std::error::Error;
-use std::num::ParseIntError;
+use std::fmt;
+use std::fmt::Formatter;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::ops::Range;
 
+/// The previous `window_size` numbers along with how many pairs of them add up to each
+/// sum, so checking a number is a single lookup and sliding the window costs O(window).
 struct Cipher {
-    history: VecDeque<i64>,
+    window_size: usize,
+    window: VecDeque<i64>,
+    pair_sums: HashMap<i64, usize>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Verdict {
+    // one of the first window_size numbers, which nothing is checked against
+    Preamble,
+    Valid,
+    // the window doesn't hold two different numbers so no pair could ever match
+    WindowTooSmall,
+    NoPair,
+}
+
+impl Verdict {
+    fn is_invalid(self) -> bool {
+        matches!(self, Verdict::WindowTooSmall | Verdict::NoPair)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Checked {
+    index: usize,
+    value: i64,
+    verdict: Verdict,
 }
 
 impl Cipher {
-    fn new(backtrack_amount: usize) -> Cipher {
-        Cipher {
-            history: VecDeque::with_capacity(backtrack_amount),
+    fn new(window_size: usize) -> Result<Cipher, AdventOfCodeError> {
+        // an empty window would never fill up, so it would grow without bound
+        if window_size == 0 {
+            return Err(AdventOfCodeError::Custom(
+                "the window must hold at least one number".to_string(),
+            ));
         }
+        Ok(Cipher {
+            window_size,
+            window: VecDeque::with_capacity(window_size),
+            pair_sums: HashMap::new(),
+        })
     }
 
     fn consume_number(&mut self, value: i64) {
-        if self.history.len() == self.history.capacity() {
-            //pop and add to mimic cycle]
-            self.history.pop_front();
+        if self.window.len() == self.window_size {
+            if let Some(oldest) = self.window.pop_front() {
+                for &other in &self.window {
+                    // the two numbers must be different
+                    if other != oldest {
+                        let sum = oldest + other;
+                        let count = self.pair_sums.get_mut(&sum).unwrap();
+                        *count -= 1;
+                        if *count == 0 {
+                            self.pair_sums.remove(&sum);
+                        }
+                    }
+                }
+            }
+        }
+        for &other in &self.window {
+            if other != value {
+                *self.pair_sums.entry(value + other).or_insert(0) += 1;
+            }
         }
-        self.history.push_back(value);
+        self.window.push_back(value);
     }
 
-    fn is_valid(&self, value: i64) -> Result<bool, UnsolvedError> {
-        if self.history.len() != self.history.capacity() {
-            return Err(UnsolvedError);
-        }
+    fn is_full(&self) -> bool {
+        self.window.len() == self.window_size
+    }
 
-        for combination in self.history.iter().combinations(2) {
-            // the two numbers must be different
-            if combination.iter().unique().count() == 1 {
-                continue;
-            }
-            let sum: i64 = combination.into_iter().sum();
-            if sum == value {
-                return Ok(true);
-            }
+    fn is_valid(&self, value: i64) -> bool {
+        self.pair_sums.contains_key(&value)
+    }
+
+    /// Checks every number against the ones before it as they arrive.
+    fn validate<I: IntoIterator<Item = i64>>(self, numbers: I) -> Validations<I::IntoIter> {
+        Validations {
+            cipher: self,
+            numbers: numbers.into_iter(),
+            index: 0,
         }
-        Ok(false)
     }
 }
 
+struct Validations<I> {
+    cipher: Cipher,
+    numbers: I,
+    index: usize,
+}
+
+impl<I: Iterator<Item = i64>> Iterator for Validations<I> {
+    type Item = Checked;
+
+    fn next(&mut self) -> Option<Checked> {
+        let value = self.numbers.next()?;
+        let verdict = if !self.cipher.is_full() {
+            Verdict::Preamble
+        } else if self.cipher.pair_sums.is_empty() {
+            Verdict::WindowTooSmall
+        } else if self.cipher.is_valid(value) {
+            Verdict::Valid
+        } else {
+            Verdict::NoPair
+        };
+        self.cipher.consume_number(value);
+        self.index += 1;
+        Some(Checked {
+            index: self.index - 1,
+            value,
+            verdict,
+        })
+    }
+}
+
+/// One number per line, read lazily.
+fn read_numbers<R: BufRead>(reader: R) -> impl Iterator<Item = Result<i64, Box<dyn Error>>> {
+    reader.lines().map(|line| Ok(line?.trim().parse::<i64>()?))
+}
+
 fn part1(input: &str, capacity: usize) -> Result<i64, Box<dyn Error>> {
-    let mut cipher = Cipher::new(capacity);
-    let numbers: Vec<i64> = input
-        .lines()
-        .map(|line| i64::from_str_radix(line, 10))
-        .collect::<Result<Vec<i64>, ParseIntError>>()?;
+    let cipher = Cipher::new(capacity)?;
+    let invalid = process_results(read_numbers(input.as_bytes()), |numbers| {
+        cipher
+            .validate(numbers)
+            .find(|checked| checked.verdict.is_invalid())
+    })?;
+
+    let invalid = invalid.ok_or(UnsolvedError)?;
+    println!("part1: {}", invalid.value);
+    Ok(invalid.value)
+}
 
-    for value in numbers.iter().take(cipher.history.capacity()) {
-        cipher.consume_number(*value);
+/// A contiguous range of at least two numbers summing to the invalid number.
+#[derive(Debug, PartialEq)]
+struct Weakness {
+    range: Range<usize>,
+    // the smallest plus the largest number in the range
+    answer: i64,
+}
+
+impl Weakness {
+    fn new(numbers: &[i64], range: Range<usize>) -> Weakness {
+        let slice = &numbers[range.clone()];
+        Weakness {
+            range,
+            answer: slice.iter().min().unwrap() + slice.iter().max().unwrap(),
+        }
     }
+}
+
+/// Finds the range ending earliest in O(n) using prefix sums: the range i..j sums to
+/// target exactly when prefix[j] - prefix[i] == target. Unlike a sliding window this also
+/// works when some numbers are negative.
+fn find_weakness(numbers: &[i64], target: i64) -> Option<Weakness> {
+    let prefix = prefix_sums(numbers);
 
-    for value in numbers.into_iter().skip(cipher.history.capacity()) {
-        let is_valid = cipher.is_valid(value)?;
-        if !is_valid {
-            println!("part1: {}", value);
-            return Ok(value);
+    // the first index each prefix sum was seen at, so the range found is the longest
+    let mut starts: HashMap<i64, usize> = HashMap::new();
+    for end in 2..=numbers.len() {
+        // only starts at least two numbers back may be used
+        starts.entry(prefix[end - 2]).or_insert(end - 2);
+        if let Some(&start) = starts.get(&(prefix[end] - target)) {
+            return Some(Weakness::new(numbers, start..end));
         }
-        cipher.consume_number(value);
     }
+    None
+}
 
-    Err(Box::new(UnsolvedError))
+fn prefix_sums(numbers: &[i64]) -> Vec<i64> {
+    // prefix[i] is the sum of numbers[..i]
+    let mut prefix = vec![0i64; numbers.len() + 1];
+    for (i, value) in numbers.iter().enumerate() {
+        prefix[i + 1] = prefix[i] + value;
+    }
+    prefix
 }
 
-fn part2(input: &str, capacity: usize) -> Result<i64, Box<dyn Error>> {
-    // this is the number to find
-    let part1_answer = part1(input, capacity)?;
+/// Prefix sums indexed by value, built once so every target in a stream can be looked up
+/// against them.
+struct RangeSums<'a> {
+    numbers: &'a [i64],
+    prefix: Vec<i64>,
+    // every index each prefix sum is seen at, in increasing order
+    starts: HashMap<i64, Vec<usize>>,
+}
+
+impl<'a> RangeSums<'a> {
+    fn new(numbers: &'a [i64]) -> RangeSums<'a> {
+        let prefix = prefix_sums(numbers);
+        let mut starts: HashMap<i64, Vec<usize>> = HashMap::new();
+        for (start, &sum) in prefix.iter().enumerate() {
+            starts.entry(sum).or_default().push(start);
+        }
+        RangeSums {
+            numbers,
+            prefix,
+            starts,
+        }
+    }
 
-    let numbers: Vec<i64> = input
-        .lines()
-        .map(|line| i64::from_str_radix(line, 10))
-        .collect::<Result<Vec<i64>, ParseIntError>>()?;
-
-    // we want to produce all contigous subsequences
-    for i in 0..numbers.len() {
-        for j in i..numbers.len() {
-            let slice = &numbers[i..j];
-            let sum: i64 = slice.iter().sum();
-            if sum == part1_answer {
-                let answer = slice.iter().max().unwrap() + slice.iter().min().unwrap();
-                println!("part2: {}", answer);
-                return Ok(answer);
+    /// Every range of at least two numbers summing to target, ordered by where they end.
+    /// With negative numbers or zeros there can be many: this takes O(n) lookups plus the
+    /// length of every range found, since each one is scanned for its min and max.
+    fn weaknesses(&self, target: i64) -> Vec<Weakness> {
+        let mut weaknesses = Vec::new();
+        for end in 2..=self.numbers.len() {
+            if let Some(found) = self.starts.get(&(self.prefix[end] - target)) {
+                // only starts at least two numbers back may be used
+                for &start in found.iter().take_while(|&&start| start + 2 <= end) {
+                    weaknesses.push(Weakness::new(self.numbers, start..end));
+                }
             }
         }
+        weaknesses
     }
+}
+
+/// A number that broke the preamble rule along with everything that could exploit it.
+#[derive(Debug, PartialEq)]
+struct Finding {
+    index: usize,
+    value: i64,
+    // indices of the numbers it was checked against
+    window: Range<usize>,
+    verdict: Verdict,
+    weaknesses: Vec<Weakness>,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let reason = match self.verdict {
+            Verdict::WindowTooSmall => "the window has fewer than two different numbers",
+            _ => "no pair in the window sums to it",
+        };
+        // line numbers, as printed by every other mode
+        writeln!(
+            f,
+            "line {} {}: {} (window lines {} to {})",
+            self.index + 1,
+            self.value,
+            reason,
+            self.window.start + 1,
+            self.window.end
+        )?;
+        if self.weaknesses.is_empty() {
+            writeln!(f, "    no range sums to it")?;
+        }
+        for weakness in &self.weaknesses {
+            writeln!(
+                f,
+                "    lines {} to {} sum to it, min + max {}",
+                weakness.range.start + 1,
+                weakness.range.end,
+                weakness.answer
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks the whole stream rather than stopping at the first invalid number.
+fn investigate(numbers: &[i64], window_size: usize) -> Result<Vec<Finding>, AdventOfCodeError> {
+    let range_sums = RangeSums::new(numbers);
+    Ok(Cipher::new(window_size)?
+        .validate(numbers.iter().copied())
+        .filter(|checked| checked.verdict.is_invalid())
+        .map(|checked| Finding {
+            index: checked.index,
+            value: checked.value,
+            window: checked.index - window_size..checked.index,
+            verdict: checked.verdict,
+            weaknesses: range_sums.weaknesses(checked.value),
+        })
+        .collect())
+}
+
+fn part2(input: &str, capacity: usize) -> Result<Weakness, Box<dyn Error>> {
+    // this is the number to find
+    let part1_answer = part1(input, capacity)?;
+
+    let numbers: Vec<i64> = read_numbers(input.as_bytes()).collect::<Result<_, _>>()?;
 
-    Err(Box::new(UnsolvedError))
+    let weakness = find_weakness(&numbers, part1_answer).ok_or(UnsolvedError)?;
+    println!(
+        "part2: {} (lines {} to {})",
+        weakness.answer,
+        weakness.range.start + 1,
+        weakness.range.end
+    );
+    Ok(weakness)
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     const MAX_CIPHER_LENGTH: usize = 25;
 
+    // report [file] lists every invalid number in the file or puzzle input, and the
+    // ranges that sum to each of them
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("report") {
+        let input = match args.get(1) {
+            Some(path) => std::fs::read_to_string(path)?,
+            None => include_str!("day9_1.txt").to_string(),
+        };
+        let numbers: Vec<i64> = read_numbers(input.as_bytes()).collect::<Result<_, _>>()?;
+        for finding in investigate(&numbers, MAX_CIPHER_LENGTH)? {
+            print!("{}", finding);
+        }
+        return Ok(());
+    }
+
+    // any other file given on the command line is streamed and every invalid number printed
+    if let Some(path) = args.first() {
+        let reader = BufReader::new(File::open(path)?);
+        let cipher = Cipher::new(MAX_CIPHER_LENGTH)?;
+        return process_results(read_numbers(reader), |numbers| {
+            cipher
+                .validate(numbers)
+                .filter(|checked| checked.verdict.is_invalid())
+                .for_each(|checked| println!("{}: {}", checked.index + 1, checked.value))
+        });
+    }
+
     let input = include_str!("day9_1.txt");
     part1(input, MAX_CIPHER_LENGTH)?;
 
@@ -105,7 +346,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
 #[cfg(test)]
 mod tests {
-    use crate::{part1, part2};
+    use crate::{find_weakness, investigate, part1, part2, Cipher, Verdict, Weakness};
 
     #[test]
     fn part1_test() {
@@ -158,6 +399,89 @@ mod tests {
 576";
         let answer = part2(sample, 5);
         assert!(answer.is_ok());
-        assert_eq!(answer.unwrap(), 62);
+        assert_eq!(
+            answer.unwrap(),
+            Weakness {
+                range: 2..6,
+                answer: 62
+            }
+        );
+    }
+
+    #[test]
+    fn weakness_needs_two_numbers() {
+        // 5 on its own doesn't count
+        assert_eq!(
+            find_weakness(&[1, 5, 2, 3], 5),
+            Some(Weakness {
+                range: 2..4,
+                answer: 5
+            })
+        );
+        assert_eq!(
+            find_weakness(&[4, -3, 7, 2], 6),
+            Some(Weakness {
+                range: 1..4,
+                answer: 4
+            })
+        );
+        assert_eq!(find_weakness(&[5], 5), None);
+    }
+
+    #[test]
+    fn streaming_window() {
+        assert!(Cipher::new(0).is_err());
+        let verdicts: Vec<Verdict> = Cipher::new(3)
+            .unwrap()
+            .validate(vec![1, 3, 3, 4, 6, 7, 3])
+            .map(|checked| checked.verdict)
+            .collect();
+        assert_eq!(
+            verdicts,
+            vec![
+                Verdict::Preamble,
+                Verdict::Preamble,
+                Verdict::Preamble,
+                Verdict::Valid,
+                // the window is 3 3 4 and a pair of the same number doesn't count
+                Verdict::NoPair,
+                Verdict::Valid,
+                // 1 + 3 only counted while 1 was still in the window
+                Verdict::NoPair,
+            ]
+        );
+    }
+
+    #[test]
+    fn forensic_report() {
+        let numbers = [1, 2, 3, -1, 4, 4, 8, 4];
+        let findings = investigate(&numbers, 2).unwrap();
+        // ranges as (start, end) pairs
+        let summary: Vec<_> = findings
+            .iter()
+            .map(|finding| {
+                let ranges = finding
+                    .weaknesses
+                    .iter()
+                    .map(|w| (w.range.start, w.range.end));
+                (finding.index, finding.verdict, ranges.collect::<Vec<_>>())
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (3, Verdict::NoPair, vec![]),
+                (4, Verdict::NoPair, vec![(1, 4)]),
+                (5, Verdict::NoPair, vec![(1, 4)]),
+                // the window is 4 4
+                (6, Verdict::WindowTooSmall, vec![(1, 5), (4, 6)]),
+                (7, Verdict::NoPair, vec![(1, 4)]),
+            ]
+        );
+        assert_eq!(findings[0].window, 1..3);
+        assert_eq!(
+            findings[0].to_string(),
+            "line 4 -1: no pair in the window sums to it (window lines 2 to 3)\n    no range sums to it\n"
+        );
     }
 }
@@ -0,0 +1,710 @@
+pub mod analysis;
+pub mod assembler;
+pub mod bytecode;
+pub mod debugger;
+pub mod repair;
+pub mod trace;
+
+use crate::handheld::trace::Trace;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::fmt::Formatter;
+use std::ops::{Index, IndexMut};
+
+// Define our error types. These may be customized for our error handling cases.
+// Now we will be able to write our own errors, defer to an underlying error
+// implementation, or do something in between.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VirtualMachineError {
+    // a pc was about to be executed a second time
+    InfiniteRecursion,
+    // the program jumped exactly one past its last instruction, ran off the end or halted
+    Finished,
+    StepLimitExceeded(usize),
+    // the instruction at pc jumped to target, which is outside the program
+    OutOfBounds { pc: usize, target: i64 },
+    // the instruction at pc overflowed a register or its jump target; handlers leave pc
+    // at 0 for the machine to fill in
+    Overflow { pc: usize },
+    Custom(String),
+}
+
+impl Error for VirtualMachineError {}
+
+impl fmt::Display for VirtualMachineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VirtualMachineError::InfiniteRecursion => write!(f, "Infinite recursion detected."),
+            VirtualMachineError::Custom(ref err) => write!(f, "Error: {}", err),
+            VirtualMachineError::Finished => write!(f, "Finished."),
+            VirtualMachineError::StepLimitExceeded(limit) => {
+                write!(f, "Step limit of {} exceeded.", limit)
+            }
+            VirtualMachineError::OutOfBounds { pc, target } => {
+                write!(f, "Jump at pc {} to {} is out of bounds.", pc, target)
+            }
+            VirtualMachineError::Overflow { pc } => write!(f, "Overflow at pc {}.", pc),
+        }
+    }
+}
+
+/// The mnemonic of an operation such as `acc` or `jmp` and how many arguments it takes.
+/// Opcodes are looked up in an [InstructionSet] to find out what they do.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Opcode {
+    pub mnemonic: &'static str,
+    // either 0 or 1
+    pub arity: usize,
+    // whether the argument is an offset the operation may jump by
+    pub jumps: bool,
+}
+
+impl Opcode {
+    pub const fn new(mnemonic: &'static str, arity: usize) -> Opcode {
+        Opcode {
+            mnemonic,
+            arity,
+            jumps: false,
+        }
+    }
+
+    pub const fn jump(mnemonic: &'static str) -> Opcode {
+        Opcode {
+            mnemonic,
+            arity: 1,
+            jumps: true,
+        }
+    }
+}
+
+pub const ACC: Opcode = Opcode::new("acc", 1);
+pub const JMP: Opcode = Opcode::jump("jmp");
+pub const NOP: Opcode = Opcode::new("nop", 1);
+pub const MUL: Opcode = Opcode::new("mul", 1);
+pub const HALT: Opcode = Opcode::new("halt", 0);
+pub const JZ: Opcode = Opcode::jump("jz");
+pub const JNZ: Opcode = Opcode::jump("jnz");
+pub const ACCB: Opcode = Opcode::new("accb", 1);
+pub const SWP: Opcode = Opcode::new("swp", 0);
+pub const OUT: Opcode = Opcode::new("out", 0);
+pub const IN: Opcode = Opcode::new("in", 0);
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.mnemonic)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Register {
+    // the accumulator, which is the only register boot code knows about
+    A,
+    B,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct Registers {
+    a: i64,
+    b: i64,
+}
+
+impl Index<Register> for Registers {
+    type Output = i64;
+
+    fn index(&self, register: Register) -> &i64 {
+        match register {
+            Register::A => &self.a,
+            Register::B => &self.b,
+        }
+    }
+}
+
+impl IndexMut<Register> for Registers {
+    fn index_mut(&mut self, register: Register) -> &mut i64 {
+        match register {
+            Register::A => &mut self.a,
+            Register::B => &mut self.b,
+        }
+    }
+}
+
+/// Everything an operation is allowed to touch besides the program counter.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Cpu {
+    pub registers: Registers,
+    pub input: VecDeque<i64>,
+    pub output: Vec<i64>,
+}
+
+/// What the machine should do with the program counter after an operation.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Flow {
+    Next,
+    // relative to the current pc
+    Jump(i64),
+    Halt,
+}
+
+/// Executes an operation with its argument. A handler that fails must leave the cpu as it
+/// found it so that the step can be retried.
+pub type Handler = fn(&mut Cpu, i64) -> Result<Flow, VirtualMachineError>;
+
+#[derive(Clone)]
+pub struct OpcodeDefinition {
+    pub opcode: Opcode,
+    pub handler: Handler,
+}
+
+/// The table of operations a [VirtualMachine] understands.
+/// New operations are added by defining them here rather than touching the machine.
+#[derive(Clone, Default)]
+pub struct InstructionSet {
+    definitions: HashMap<&'static str, OpcodeDefinition>,
+}
+
+impl InstructionSet {
+    /// The three operations of the handheld boot code.
+    pub fn boot_code() -> InstructionSet {
+        let mut set = InstructionSet::default();
+        set.define(ACC, |cpu, arg| {
+            cpu.registers[Register::A] = cpu.registers[Register::A]
+                .checked_add(arg)
+                .ok_or(VirtualMachineError::Overflow { pc: 0 })?;
+            Ok(Flow::Next)
+        });
+        set.define(JMP, |_, arg| Ok(Flow::Jump(arg)));
+        set.define(NOP, |_, _| Ok(Flow::Next));
+        set
+    }
+
+    /// Boot code plus a second register, conditional jumps, multiplication, halting and I/O.
+    pub fn extended() -> InstructionSet {
+        let mut set = InstructionSet::boot_code();
+        set.define(MUL, |cpu, arg| {
+            cpu.registers[Register::A] = cpu.registers[Register::A]
+                .checked_mul(arg)
+                .ok_or(VirtualMachineError::Overflow { pc: 0 })?;
+            Ok(Flow::Next)
+        });
+        set.define(HALT, |_, _| Ok(Flow::Halt));
+        set.define(JZ, |cpu, arg| match cpu.registers[Register::A] {
+            0 => Ok(Flow::Jump(arg)),
+            _ => Ok(Flow::Next),
+        });
+        set.define(JNZ, |cpu, arg| match cpu.registers[Register::A] {
+            0 => Ok(Flow::Next),
+            _ => Ok(Flow::Jump(arg)),
+        });
+        set.define(ACCB, |cpu, arg| {
+            cpu.registers[Register::B] = cpu.registers[Register::B]
+                .checked_add(arg)
+                .ok_or(VirtualMachineError::Overflow { pc: 0 })?;
+            Ok(Flow::Next)
+        });
+        set.define(SWP, |cpu, _| {
+            let a = cpu.registers[Register::A];
+            cpu.registers[Register::A] = cpu.registers[Register::B];
+            cpu.registers[Register::B] = a;
+            Ok(Flow::Next)
+        });
+        set.define(OUT, |cpu, _| {
+            cpu.output.push(cpu.registers[Register::A]);
+            Ok(Flow::Next)
+        });
+        set.define(IN, |cpu, _| {
+            cpu.registers[Register::A] = cpu
+                .input
+                .pop_front()
+                .ok_or(VirtualMachineError::Custom("Input is empty".to_string()))?;
+            Ok(Flow::Next)
+        });
+        set
+    }
+
+    pub fn define(&mut self, opcode: Opcode, handler: Handler) {
+        self.definitions
+            .insert(opcode.mnemonic, OpcodeDefinition { opcode, handler });
+    }
+
+    pub fn get(&self, mnemonic: &str) -> Option<&OpcodeDefinition> {
+        self.definitions.get(mnemonic)
+    }
+
+    pub fn parse(&self, line: &str) -> Result<Instruction, VirtualMachineError> {
+        let parts: Vec<&str> = line.split_ascii_whitespace().collect();
+        let mnemonic = parts
+            .first()
+            .ok_or(VirtualMachineError::Custom("Missing operation".to_string()))?;
+        let definition = self
+            .get(mnemonic)
+            .ok_or(VirtualMachineError::Custom(format!(
+                "Unknown operation: {}",
+                mnemonic
+            )))?;
+        if parts.len() != definition.opcode.arity + 1 {
+            return Err(VirtualMachineError::Custom(format!(
+                "{} takes {} argument(s)",
+                mnemonic, definition.opcode.arity
+            )));
+        }
+        let arg = match parts.get(1) {
+            Some(arg) => arg
+                .parse::<i64>()
+                .map_err(|_| VirtualMachineError::Custom("Could not parse argument".to_string()))?,
+            None => 0,
+        };
+        Ok(Instruction {
+            op: definition.opcode,
+            arg,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Instruction {
+    pub op: Opcode,
+    pub arg: i64,
+}
+
+impl Instruction {
+    /// Parses a line of boot code.
+    pub fn from_line(line: &str) -> Result<Instruction, VirtualMachineError> {
+        InstructionSet::boot_code().parse(line)
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.op.arity {
+            0 => write!(f, "{}", self.op),
+            _ => write!(f, "{} {:+}", self.op, self.arg),
+        }
+    }
+}
+
+/// Which checks a [VirtualMachine] makes to decide that a program has gone wrong.
+#[derive(Debug, PartialEq, Clone)]
+pub struct HaltingPolicy {
+    // boot code has no conditional jumps so revisiting any pc means it will loop forever
+    pub detect_loops: bool,
+    // stop once this many instructions have been executed
+    pub max_steps: Option<usize>,
+    // when false a jump outside the program finishes it like a jump to the end would
+    pub check_bounds: bool,
+}
+
+impl HaltingPolicy {
+    /// The handheld semantics: loops are errors and so are wild jumps.
+    pub fn boot_code() -> HaltingPolicy {
+        HaltingPolicy {
+            detect_loops: true,
+            max_steps: None,
+            check_bounds: true,
+        }
+    }
+
+    /// For programs with conditional jumps, which may legitimately revisit a pc,
+    /// so only the number of steps bounds them.
+    pub fn fuel(max_steps: usize) -> HaltingPolicy {
+        HaltingPolicy {
+            detect_loops: false,
+            max_steps: Some(max_steps),
+            check_bounds: true,
+        }
+    }
+}
+
+/// Everything needed to put a [VirtualMachine] back the way it was, apart from its program.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Snapshot {
+    pc: usize,
+    cpu: Cpu,
+    seen_pc: HashSet<usize>,
+    steps: usize,
+    halted: bool,
+    trace_len: usize,
+    undo_len: usize,
+}
+
+// what a single step changed, so it can be undone without copying the whole machine
+#[derive(Debug, PartialEq, Clone)]
+pub struct UndoEntry {
+    pc: usize,
+    registers: Registers,
+    output_len: usize,
+    input_len: usize,
+    input_front: Option<i64>,
+    first_visit: bool,
+    trace_len: usize,
+}
+
+pub struct VirtualMachine {
+    pub pc: usize,
+    pub cpu: Cpu,
+    pub instructions: Vec<Instruction>,
+    pub seen_pc: HashSet<usize>,
+    // how many instructions have been executed
+    pub steps: usize,
+    pub policy: HaltingPolicy,
+    // only recorded when enabled as it grows with every step
+    pub trace: Option<Trace>,
+    // only recorded when enabled; allows stepping backwards
+    pub undo_log: Option<Vec<UndoEntry>>,
+    instruction_set: InstructionSet,
+    halted: bool,
+}
+
+impl VirtualMachine {
+    /// Loads a program of boot code.
+    pub fn new(input: &str) -> Result<VirtualMachine, VirtualMachineError> {
+        VirtualMachine::with_instruction_set(input, InstructionSet::boot_code())
+    }
+
+    pub fn with_instruction_set(
+        input: &str,
+        instruction_set: InstructionSet,
+    ) -> Result<VirtualMachine, VirtualMachineError> {
+        let instructions: Vec<Instruction> = input
+            .lines()
+            .map(|line| instruction_set.parse(line))
+            .collect::<Result<Vec<Instruction>, VirtualMachineError>>()?;
+        Ok(VirtualMachine::from_instructions(
+            instructions,
+            instruction_set,
+        ))
+    }
+
+    pub fn from_instructions(
+        instructions: Vec<Instruction>,
+        instruction_set: InstructionSet,
+    ) -> VirtualMachine {
+        VirtualMachine {
+            pc: 0,
+            cpu: Cpu::default(),
+            instructions,
+            seen_pc: HashSet::new(),
+            steps: 0,
+            policy: HaltingPolicy::boot_code(),
+            trace: None,
+            undo_log: None,
+            instruction_set,
+            halted: false,
+        }
+    }
+
+    pub fn acc(&self) -> i64 {
+        self.cpu.registers[Register::A]
+    }
+
+    pub fn instruction_set(&self) -> &InstructionSet {
+        &self.instruction_set
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Puts the machine back at the start of its (possibly patched) program.
+    pub fn reset(&mut self) {
+        self.pc = 0;
+        self.cpu = Cpu::default();
+        self.seen_pc.clear();
+        self.steps = 0;
+        self.halted = false;
+        if self.trace.is_some() {
+            self.trace = Some(Trace::default());
+        }
+        if self.undo_log.is_some() {
+            self.undo_log = Some(Vec::new());
+        }
+    }
+
+    fn trace_len(&self) -> usize {
+        self.trace.as_ref().map_or(0, |trace| trace.entries.len())
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            pc: self.pc,
+            cpu: self.cpu.clone(),
+            seen_pc: self.seen_pc.clone(),
+            steps: self.steps,
+            halted: self.halted,
+            trace_len: self.trace_len(),
+            undo_len: self.undo_log.as_ref().map_or(0, |log| log.len()),
+        }
+    }
+
+    /// Rewinds to a snapshot taken earlier, dropping any trace or undo history since.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.pc = snapshot.pc;
+        self.cpu = snapshot.cpu.clone();
+        self.seen_pc = snapshot.seen_pc.clone();
+        self.steps = snapshot.steps;
+        self.halted = snapshot.halted;
+        if let Some(trace) = &mut self.trace {
+            trace.entries.truncate(snapshot.trace_len);
+            trace.looped = false;
+        }
+        if let Some(log) = &mut self.undo_log {
+            log.truncate(snapshot.undo_len);
+        }
+    }
+
+    /// Undoes the last step; the undo log must have been enabled before taking it.
+    pub fn step_back(&mut self) -> Result<(), VirtualMachineError> {
+        let entry = self
+            .undo_log
+            .as_mut()
+            .and_then(|log| log.pop())
+            .ok_or(VirtualMachineError::Custom("Nothing to undo".to_string()))?;
+
+        self.pc = entry.pc;
+        self.cpu.registers = entry.registers;
+        self.cpu.output.truncate(entry.output_len);
+        if self.cpu.input.len() < entry.input_len {
+            if let Some(value) = entry.input_front {
+                self.cpu.input.push_front(value);
+            }
+        }
+        if entry.first_visit {
+            self.seen_pc.remove(&entry.pc);
+        }
+        self.steps -= 1;
+        self.halted = false;
+        if let Some(trace) = &mut self.trace {
+            trace.entries.truncate(entry.trace_len);
+            trace.looped = false;
+        }
+        Ok(())
+    }
+
+    /// Executes a single instruction.
+    /// Returns [VirtualMachineError::Finished] once the program has run off the end or halted,
+    /// otherwise any error the [HaltingPolicy] calls for before executing the instruction.
+    pub fn step(&mut self) -> Result<(), VirtualMachineError> {
+        if self.halted {
+            return Err(VirtualMachineError::Finished);
+        }
+
+        let inst: &Instruction = self
+            .instructions
+            .get(self.pc)
+            .ok_or(VirtualMachineError::Finished)?;
+
+        if self.policy.detect_loops && self.seen_pc.contains(&self.pc) {
+            if let Some(trace) = &mut self.trace {
+                trace.looped = true;
+            }
+            return Err(VirtualMachineError::InfiniteRecursion);
+        }
+        if let Some(limit) = self.policy.max_steps {
+            if self.steps >= limit {
+                return Err(VirtualMachineError::StepLimitExceeded(limit));
+            }
+        }
+
+        let definition =
+            self.instruction_set
+                .get(inst.op.mnemonic)
+                .ok_or(VirtualMachineError::Custom(format!(
+                    "Unknown operation: {}",
+                    inst.op
+                )))?;
+
+        let pc = self.pc;
+        let undo = self.undo_log.as_ref().map(|_| UndoEntry {
+            pc,
+            registers: self.cpu.registers.clone(),
+            output_len: self.cpu.output.len(),
+            input_len: self.cpu.input.len(),
+            input_front: self.cpu.input.front().copied(),
+            first_visit: !self.seen_pc.contains(&pc),
+            trace_len: self.trace_len(),
+        });
+        let acc_before = self.cpu.registers[Register::A];
+
+        // nothing is recorded until the instruction has run, so a failed step can be retried
+        let overflow = VirtualMachineError::Overflow { pc };
+        let flow = (definition.handler)(&mut self.cpu, inst.arg).map_err(|err| match err {
+            VirtualMachineError::Overflow { .. } => overflow.clone(),
+            err => err,
+        })?;
+        let next_pc = match flow {
+            Flow::Next => Some(pc as i64 + 1),
+            Flow::Jump(offset) => Some((pc as i64).checked_add(offset).ok_or(overflow)?),
+            Flow::Halt => None,
+        };
+
+        if let (Some(log), Some(undo)) = (&mut self.undo_log, undo) {
+            log.push(undo);
+        }
+        self.seen_pc.insert(pc);
+        self.steps += 1;
+        if let Some(trace) = &mut self.trace {
+            trace.record(
+                self.pc,
+                inst.clone(),
+                acc_before,
+                self.cpu.registers[Register::A],
+                next_pc,
+            );
+        }
+
+        let next_pc = match next_pc {
+            Some(next_pc) => next_pc,
+            None => {
+                self.halted = true;
+                return Ok(());
+            }
+        };
+
+        // one past the end is how a program is meant to finish
+        let end = self.instructions.len() as i64;
+        if next_pc < 0 || next_pc > end {
+            if self.policy.check_bounds {
+                return Err(VirtualMachineError::OutOfBounds {
+                    pc: self.pc,
+                    target: next_pc,
+                });
+            }
+            self.halted = true;
+            return Ok(());
+        }
+        self.pc = next_pc as usize;
+        Ok(())
+    }
+
+    /// Runs the program until it finishes, returning any other error along the way.
+    pub fn execute(&mut self) -> Result<(), VirtualMachineError> {
+        loop {
+            match self.step() {
+                Ok(()) => {}
+                Err(VirtualMachineError::Finished) => return Ok(()),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handheld::{
+        HaltingPolicy, Instruction, InstructionSet, VirtualMachine, VirtualMachineError, ACC, HALT,
+    };
+
+    #[test]
+    fn instruction_display() {
+        let instruction = Instruction { op: ACC, arg: 3 };
+        assert_eq!(instruction.to_string(), "acc +3");
+        let instruction = Instruction { op: HALT, arg: 0 };
+        assert_eq!(instruction.to_string(), "halt");
+        assert!(Instruction::from_line("halt").is_err());
+    }
+
+    #[test]
+    fn extended_program() {
+        // adds 2 for every count of the input then scales the sum by 10
+        let program = "in
+swp
+acc +2
+swp
+acc -1
+jnz -4
+swp
+mul +10
+out
+halt
+acc +100";
+        let mut vm =
+            VirtualMachine::with_instruction_set(program, InstructionSet::extended()).unwrap();
+        vm.policy = HaltingPolicy::fuel(1000);
+        vm.cpu.input.push_back(4);
+        assert_eq!(vm.execute(), Ok(()));
+        assert_eq!(vm.cpu.output, vec![80]);
+        assert_eq!(vm.acc(), 80);
+        assert_eq!(vm.step(), Err(VirtualMachineError::Finished));
+    }
+
+    #[test]
+    fn halting_semantics() {
+        // a revisit through fallthrough is a loop too, not just one through a jump
+        let mut vm = VirtualMachine::new("jmp +2\nacc +1\njmp -1").unwrap();
+        assert_eq!(vm.execute(), Err(VirtualMachineError::InfiniteRecursion));
+        assert_eq!((vm.pc, vm.acc()), (2, 1));
+
+        let mut vm = VirtualMachine::new("acc +1\njmp +1").unwrap();
+        assert_eq!(vm.execute(), Ok(()));
+
+        let mut vm = VirtualMachine::new("acc +1\njmp -2").unwrap();
+        assert_eq!(
+            vm.execute(),
+            Err(VirtualMachineError::OutOfBounds { pc: 1, target: -1 })
+        );
+        let mut vm = VirtualMachine::new("acc +1\njmp +5").unwrap();
+        vm.policy.check_bounds = false;
+        assert_eq!(vm.execute(), Ok(()));
+
+        let mut vm = VirtualMachine::new("acc +1\njmp -1").unwrap();
+        vm.policy = HaltingPolicy::fuel(5);
+        assert_eq!(vm.execute(), Err(VirtualMachineError::StepLimitExceeded(5)));
+        assert_eq!(vm.acc(), 3);
+    }
+
+    #[test]
+    fn overflow() {
+        let program = "acc +9223372036854775807\nmul +2";
+        let mut vm =
+            VirtualMachine::with_instruction_set(program, InstructionSet::extended()).unwrap();
+        assert_eq!(vm.execute(), Err(VirtualMachineError::Overflow { pc: 1 }));
+
+        let mut vm = VirtualMachine::new("nop +0\njmp +9223372036854775807").unwrap();
+        assert_eq!(vm.execute(), Err(VirtualMachineError::Overflow { pc: 1 }));
+    }
+
+    #[test]
+    fn snapshots_and_undo() {
+        let program = "in\nacc +2\nout\nacc -10\nout";
+        let mut vm =
+            VirtualMachine::with_instruction_set(program, InstructionSet::extended()).unwrap();
+        vm.cpu.input.push_back(5);
+        vm.undo_log = Some(Vec::new());
+
+        vm.step().unwrap();
+        let snapshot = vm.snapshot();
+        assert_eq!(vm.execute(), Ok(()));
+        assert_eq!(vm.cpu.output, vec![7, -3]);
+
+        vm.restore(&snapshot);
+        assert_eq!((vm.pc, vm.acc(), vm.steps), (1, 5, 1));
+        assert!(vm.cpu.output.is_empty());
+
+        vm.step().unwrap();
+        vm.step().unwrap();
+        vm.step_back().unwrap();
+        vm.step_back().unwrap();
+        vm.step_back().unwrap();
+        assert_eq!((vm.pc, vm.acc(), vm.cpu.input.len()), (0, 0, 1));
+        assert!(vm.seen_pc.is_empty());
+        assert!(vm.step_back().is_err());
+    }
+
+    #[test]
+    fn retry_after_failed_step() {
+        let mut vm =
+            VirtualMachine::with_instruction_set("in\nout", InstructionSet::extended()).unwrap();
+        vm.undo_log = Some(Vec::new());
+        assert_eq!(
+            vm.step(),
+            Err(VirtualMachineError::Custom("Input is empty".to_string()))
+        );
+        assert_eq!((vm.pc, vm.steps), (0, 0));
+        assert!(vm.seen_pc.is_empty());
+        assert!(vm.step_back().is_err());
+
+        vm.cpu.input.push_back(7);
+        assert_eq!(vm.execute(), Ok(()));
+        assert_eq!(vm.cpu.output, vec![7]);
+    }
+}
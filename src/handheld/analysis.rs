@@ -0,0 +1,342 @@
+use crate::handheld::{Instruction, HALT, JMP, NOP};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fmt::{Formatter, Write};
+
+/// What can be learned about a program without running it.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Report {
+    // pcs that no path from the entry point reaches
+    pub unreachable: Vec<usize>,
+    // (pc, target) for jumps landing outside the program, other than exactly at its end
+    pub out_of_range_jumps: Vec<(usize, i64)>,
+    // cycles reachable from the entry point that have no way out once entered
+    pub infinite_loops: Vec<Vec<usize>>,
+    // (pc, target) for nops that would be a valid jump if flipped
+    pub jumpable_nops: Vec<(usize, i64)>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for pc in &self.unreachable {
+            writeln!(f, "unreachable: {}", pc)?;
+        }
+        for (pc, target) in &self.out_of_range_jumps {
+            writeln!(f, "out of range jump: {} -> {}", pc, target)?;
+        }
+        for cycle in &self.infinite_loops {
+            let pcs: Vec<String> = cycle.iter().map(|pc| pc.to_string()).collect();
+            writeln!(f, "infinite loop: {}", pcs.join(" -> "))?;
+        }
+        for (pc, target) in &self.jumpable_nops {
+            writeln!(f, "nop could jump: {} -> {}", pc, target)?;
+        }
+        Ok(())
+    }
+}
+
+/// Every pc control may go to after the instruction at pc, which may be out of range.
+/// A program finishes by going to exactly its length.
+pub fn successors(pc: usize, instruction: &Instruction) -> Vec<i64> {
+    let next = pc as i64 + 1;
+    let target = jump_target(pc, instruction.arg);
+    match instruction.op {
+        HALT => vec![],
+        JMP => vec![target],
+        // any other jump is conditional so it may fall through instead
+        op if op.jumps => vec![next, target],
+        _ => vec![next],
+    }
+}
+
+/// Where a jump by offset from pc lands. A target past what an i64 holds is out of
+/// range of any program either way, so it saturates rather than overflowing.
+pub fn jump_target(pc: usize, offset: i64) -> i64 {
+    (pc as i64).saturating_add(offset)
+}
+
+pub fn analyze(instructions: &[Instruction]) -> Report {
+    let end = instructions.len() as i64;
+    let in_program = |target: i64| target >= 0 && target < end;
+    let mut report = Report::default();
+
+    let reachable = reachable(instructions);
+    for (pc, instruction) in instructions.iter().enumerate() {
+        if !reachable[pc] {
+            report.unreachable.push(pc);
+        }
+        for target in successors(pc, instruction) {
+            if !in_program(target) && target != end {
+                report.out_of_range_jumps.push((pc, target));
+            }
+        }
+        // flipping a nop +0 would only make a jump to itself
+        if instruction.op == NOP && instruction.arg != 0 {
+            let target = jump_target(pc, instruction.arg);
+            if in_program(target) || target == end {
+                report.jumpable_nops.push((pc, target));
+            }
+        }
+    }
+
+    // a strongly connected component nothing leaves is a loop that never ends
+    let components = strongly_connected_components(instructions);
+    let mut component_of = vec![0; instructions.len()];
+    for (id, component) in components.iter().enumerate() {
+        for &pc in component {
+            component_of[pc] = id;
+        }
+    }
+    for (id, component) in components.iter().enumerate() {
+        let first = component[0];
+        if !reachable[first] {
+            continue;
+        }
+        let is_cycle = component.len() > 1
+            || successors(first, &instructions[first]).contains(&(first as i64));
+        let closed = component.iter().all(|&pc| {
+            successors(pc, &instructions[pc])
+                .iter()
+                .all(|&target| in_program(target) && component_of[target as usize] == id)
+        });
+        if is_cycle && closed {
+            report
+                .infinite_loops
+                .push(execution_order(instructions, component));
+        }
+    }
+    report.infinite_loops.sort();
+
+    report
+}
+
+fn reachable(instructions: &[Instruction]) -> Vec<bool> {
+    let end = instructions.len() as i64;
+    let mut reachable = vec![false; instructions.len()];
+    if instructions.is_empty() {
+        return reachable;
+    }
+    let mut queue: VecDeque<usize> = VecDeque::new();
+    reachable[0] = true;
+    queue.push_back(0);
+    while let Some(pc) = queue.pop_front() {
+        for target in successors(pc, &instructions[pc]) {
+            if target >= 0 && target < end && !reachable[target as usize] {
+                reachable[target as usize] = true;
+                queue.push_back(target as usize);
+            }
+        }
+    }
+    reachable
+}
+
+// lists a closed loop starting from its lowest pc in the order it would run
+fn execution_order(instructions: &[Instruction], component: &[usize]) -> Vec<usize> {
+    let start = *component.iter().min().unwrap();
+    let mut order = vec![start];
+    let mut pc = start;
+    loop {
+        // loops without conditional jumps have exactly one way forward
+        let next = successors(pc, &instructions[pc])[0] as usize;
+        if next == start || order.len() == component.len() {
+            break;
+        }
+        order.push(next);
+        pc = next;
+    }
+    if order.len() != component.len() {
+        // a conditional jump branches inside the loop, so there isn't one order
+        let mut sorted = component.to_vec();
+        sorted.sort();
+        return sorted;
+    }
+    order
+}
+
+// Tarjan's algorithm over the in range edges. The depth first search keeps its own stack
+// of frames rather than recursing, so long straight line programs can't overflow the
+// call stack.
+fn strongly_connected_components(instructions: &[Instruction]) -> Vec<Vec<usize>> {
+    let len = instructions.len() as i64;
+    let in_range = |pc: usize| {
+        successors(pc, &instructions[pc])
+            .into_iter()
+            .filter(|&target| target >= 0 && target < len)
+            .map(|target| target as usize)
+            .collect::<Vec<usize>>()
+    };
+
+    let mut index: Vec<Option<usize>> = vec![None; instructions.len()];
+    let mut low = vec![0; instructions.len()];
+    let mut on_stack = vec![false; instructions.len()];
+    let mut stack: Vec<usize> = Vec::new();
+    let mut next_index = 0;
+    let mut components: Vec<Vec<usize>> = Vec::new();
+
+    for root in 0..instructions.len() {
+        if index[root].is_some() {
+            continue;
+        }
+        // each frame is a pc being visited, its successors and how many were looked at
+        let mut frames: Vec<(usize, Vec<usize>, usize)> = Vec::new();
+        index[root] = Some(next_index);
+        low[root] = next_index;
+        next_index += 1;
+        stack.push(root);
+        on_stack[root] = true;
+        frames.push((root, in_range(root), 0));
+
+        while let Some((pc, targets, next)) = frames.last_mut() {
+            let pc = *pc;
+            if let Some(&target) = targets.get(*next) {
+                *next += 1;
+                match index[target] {
+                    None => {
+                        index[target] = Some(next_index);
+                        low[target] = next_index;
+                        next_index += 1;
+                        stack.push(target);
+                        on_stack[target] = true;
+                        frames.push((target, in_range(target), 0));
+                    }
+                    Some(target_index) if on_stack[target] => {
+                        low[pc] = low[pc].min(target_index);
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+
+            // every successor is done, so pc's component is complete if it's the root
+            frames.pop();
+            if let Some((parent, _, _)) = frames.last() {
+                low[*parent] = low[*parent].min(low[pc]);
+            }
+            if Some(low[pc]) == index[pc] {
+                let mut component = Vec::new();
+                while let Some(member) = stack.pop() {
+                    on_stack[member] = false;
+                    component.push(member);
+                    if member == pc {
+                        break;
+                    }
+                }
+                components.push(component);
+            }
+        }
+    }
+    components
+}
+
+/// The control flow graph in GraphViz DOT. Unreachable instructions are grey and
+/// jumps out of range go to a red "invalid" node.
+pub fn to_dot(instructions: &[Instruction]) -> String {
+    let end = instructions.len() as i64;
+    let reachable = reachable(instructions);
+    let mut dot = String::from("digraph program {\n");
+    writeln!(dot, "    end [shape=doublecircle];").unwrap();
+    let mut invalid = false;
+    for (pc, instruction) in instructions.iter().enumerate() {
+        let style = if reachable[pc] {
+            ""
+        } else {
+            ", style=filled, fillcolor=grey"
+        };
+        writeln!(
+            dot,
+            "    n{} [shape=box, label=\"{}: {}\"{}];",
+            pc, pc, instruction, style
+        )
+        .unwrap();
+
+        for target in successors(pc, instruction) {
+            let label = if target == pc as i64 + 1 && instruction.op != JMP {
+                ""
+            } else {
+                " [label=\"jump\"]"
+            };
+            if target >= 0 && target < end {
+                writeln!(dot, "    n{} -> n{}{};", pc, target, label).unwrap();
+            } else if target == end {
+                writeln!(dot, "    n{} -> end{};", pc, label).unwrap();
+            } else {
+                invalid = true;
+                writeln!(dot, "    n{} -> invalid{};", pc, label).unwrap();
+            }
+        }
+    }
+    if invalid {
+        writeln!(dot, "    invalid [shape=octagon, color=red];").unwrap();
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handheld::analysis::{analyze, to_dot, Report};
+    use crate::handheld::{Instruction, InstructionSet};
+
+    fn parse(program: &str) -> Vec<Instruction> {
+        let instruction_set = InstructionSet::extended();
+        program
+            .lines()
+            .map(|line| instruction_set.parse(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn sample_report() {
+        let instructions = parse(
+            "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6",
+        );
+        assert_eq!(
+            analyze(&instructions),
+            Report {
+                unreachable: vec![5, 8],
+                out_of_range_jumps: vec![],
+                infinite_loops: vec![vec![1, 2, 6, 7, 3, 4]],
+                jumpable_nops: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn conditional_loops_are_not_guaranteed() {
+        let instructions = parse(
+            "nop +4
+acc +3
+acc -1
+jnz -1
+jmp -8
+halt",
+        );
+        let report = analyze(&instructions);
+        assert!(report.infinite_loops.is_empty());
+        assert_eq!(report.out_of_range_jumps, vec![(4, -4)]);
+        assert_eq!(report.unreachable, vec![5]);
+        assert_eq!(report.jumpable_nops, vec![(0, 4)]);
+        assert!(to_dot(&instructions).contains("    n4 -> invalid [label=\"jump\"];\n"));
+
+        let report = analyze(&parse("nop +0\njmp +9223372036854775807"));
+        assert_eq!(report.out_of_range_jumps, vec![(1, i64::MAX)]);
+    }
+
+    #[test]
+    fn long_programs() {
+        // deep enough to overflow the stack if the search recursed per instruction
+        let mut program = "acc +1\n".repeat(200_000);
+        program.push_str("jmp -200000");
+        let report = analyze(&parse(&program));
+        assert_eq!(report.infinite_loops.len(), 1);
+        assert_eq!(report.infinite_loops[0].len(), 200_001);
+    }
+}
@@ -0,0 +1,247 @@
+use crate::handheld::analysis::jump_target;
+use crate::handheld::{Instruction, InstructionSet, VirtualMachineError};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Turns hand written assembly into instructions.
+///
+/// On top of plain boot code the source may contain:
+/// - blank lines and comments starting with `#` or `;`
+/// - labels such as `loop_start:`, either on their own line or before an instruction
+/// - constants such as `.const STEP 4`
+///
+/// An argument naming a label becomes the relative offset from the instruction to the
+/// label, so `jmp loop_start` jumps there. An argument naming a constant becomes its
+/// value and may be negated with a leading `-`.
+pub struct Assembler {
+    instruction_set: InstructionSet,
+}
+
+// a line that will become an instruction, kept for the second pass
+struct Statement<'a> {
+    line: usize,
+    mnemonic: &'a str,
+    argument: Option<&'a str>,
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn error(line: usize, message: String) -> VirtualMachineError {
+    VirtualMachineError::Custom(format!("line {}: {}", line, message))
+}
+
+impl Assembler {
+    pub fn new(instruction_set: InstructionSet) -> Assembler {
+        Assembler { instruction_set }
+    }
+
+    pub fn assemble(&self, source: &str) -> Result<Vec<Instruction>, VirtualMachineError> {
+        let mut labels: HashMap<&str, usize> = HashMap::new();
+        let mut constants: HashMap<&str, i64> = HashMap::new();
+        let mut statements: Vec<Statement> = Vec::new();
+
+        // first pass finds where every label points and what every constant is
+        for (i, line) in source.lines().enumerate() {
+            let line_number = i + 1;
+            let mut code = line.split(&['#', ';'][..]).next().unwrap_or("").trim();
+
+            if let Some(rest) = code.strip_prefix(".const") {
+                let parts: Vec<&str> = rest.split_ascii_whitespace().collect();
+                if parts.len() != 2 || !is_identifier(parts[0]) {
+                    return Err(error(
+                        line_number,
+                        "expected .const <name> <value>".to_string(),
+                    ));
+                }
+                let value = parts[1].parse::<i64>().map_err(|_| {
+                    error(line_number, format!("invalid constant value {}", parts[1]))
+                })?;
+                if constants.insert(parts[0], value).is_some() {
+                    return Err(error(
+                        line_number,
+                        format!("constant {} is already defined", parts[0]),
+                    ));
+                }
+                continue;
+            }
+
+            if let Some((label, rest)) = code.split_once(':') {
+                let label = label.trim();
+                if !is_identifier(label) {
+                    return Err(error(line_number, format!("invalid label {}", label)));
+                }
+                if labels.insert(label, statements.len()).is_some() {
+                    return Err(error(
+                        line_number,
+                        format!("label {} is already defined", label),
+                    ));
+                }
+                code = rest.trim();
+            }
+
+            if code.is_empty() {
+                continue;
+            }
+            let parts: Vec<&str> = code.split_ascii_whitespace().collect();
+            if parts.len() > 2 {
+                return Err(error(
+                    line_number,
+                    format!("unexpected {}", parts[2..].join(" ")),
+                ));
+            }
+            statements.push(Statement {
+                line: line_number,
+                mnemonic: parts[0],
+                argument: parts.get(1).copied(),
+            });
+        }
+
+        for name in labels.keys() {
+            if constants.contains_key(name) {
+                return Err(VirtualMachineError::Custom(format!(
+                    "{} is both a label and a constant",
+                    name
+                )));
+            }
+        }
+
+        // second pass resolves every argument now that all names are known
+        statements
+            .iter()
+            .enumerate()
+            .map(|(address, statement)| {
+                let definition = self.instruction_set.get(statement.mnemonic).ok_or(error(
+                    statement.line,
+                    format!("unknown operation {}", statement.mnemonic),
+                ))?;
+                let opcode = definition.opcode;
+                let arg = match (opcode.arity, statement.argument) {
+                    (0, None) => 0,
+                    (1, Some(argument)) => {
+                        self.resolve(argument, address, &labels, &constants, statement.line)?
+                    }
+                    _ => {
+                        return Err(error(
+                            statement.line,
+                            format!("{} takes {} argument(s)", opcode, opcode.arity),
+                        ))
+                    }
+                };
+                Ok(Instruction { op: opcode, arg })
+            })
+            .collect()
+    }
+
+    fn resolve(
+        &self,
+        argument: &str,
+        address: usize,
+        labels: &HashMap<&str, usize>,
+        constants: &HashMap<&str, i64>,
+        line: usize,
+    ) -> Result<i64, VirtualMachineError> {
+        if let Ok(value) = argument.parse::<i64>() {
+            return Ok(value);
+        }
+        if let Some(target) = labels.get(argument) {
+            return Ok(*target as i64 - address as i64);
+        }
+        let (sign, name) = match argument.strip_prefix('-') {
+            Some(name) => (-1, name),
+            None => (1, argument),
+        };
+        let value = constants.get(name).ok_or(error(
+            line,
+            format!("unknown label or constant {}", argument),
+        ))?;
+        value
+            .checked_mul(sign)
+            .ok_or(error(line, format!("{} overflows", argument)))
+    }
+}
+
+/// Lists a program with the address of every instruction and where each jump lands.
+pub fn disassemble(instructions: &[Instruction]) -> String {
+    let end = instructions.len() as i64;
+    let mut listing = String::new();
+    for (address, instruction) in instructions.iter().enumerate() {
+        write!(listing, "{:>5}: {}", address, instruction).unwrap();
+        if instruction.op.jumps {
+            let target = jump_target(address, instruction.arg);
+            if target == end {
+                write!(listing, "\t; -> {} (end)", target).unwrap();
+            } else if target < 0 || target > end {
+                write!(listing, "\t; -> {} (out of range)", target).unwrap();
+            } else {
+                write!(listing, "\t; -> {}", target).unwrap();
+            }
+        }
+        listing.push('\n');
+    }
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handheld::assembler::{disassemble, Assembler};
+    use crate::handheld::{Instruction, InstructionSet, VirtualMachine, ACC, JMP, NOP};
+
+    #[test]
+    fn labels_and_constants() {
+        let source = "# fixed version of the sample program
+.const BONUS 6
+
+start:
+    nop +0      ; does nothing
+    acc +1
+    jmp skip
+back: acc +3
+    jmp start
+    acc -99
+skip:
+    acc +1
+    nop back
+    acc BONUS
+";
+        let instructions = Assembler::new(InstructionSet::boot_code())
+            .assemble(source)
+            .unwrap();
+        assert_eq!(instructions[2], Instruction { op: JMP, arg: 4 });
+        assert_eq!(instructions[4], Instruction { op: JMP, arg: -4 });
+        assert_eq!(instructions[7], Instruction { op: NOP, arg: -4 });
+        assert_eq!(instructions[8], Instruction { op: ACC, arg: 6 });
+
+        let mut vm = VirtualMachine::from_instructions(instructions, InstructionSet::boot_code());
+        assert!(vm.execute().is_ok());
+        assert_eq!(vm.acc(), 8);
+    }
+
+    #[test]
+    fn errors_and_disassembly() {
+        let assembler = Assembler::new(InstructionSet::boot_code());
+        assert_eq!(
+            assembler
+                .assemble("acc +1\njmp nowhere")
+                .unwrap_err()
+                .to_string(),
+            "Error: line 2: unknown label or constant nowhere"
+        );
+        assert!(assembler.assemble("halt").is_err());
+
+        let instructions = assembler.assemble("nop +0\njmp +2\njmp -5").unwrap();
+        assert_eq!(
+            disassemble(&instructions),
+            "    0: nop +0
+    1: jmp +2\t; -> 3 (end)
+    2: jmp -5\t; -> -3 (out of range)
+"
+        );
+    }
+}
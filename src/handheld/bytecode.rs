@@ -0,0 +1,219 @@
+use crate::handheld::{Instruction, InstructionSet, Opcode, VirtualMachineError};
+
+/// Bytecode layout, version 1:
+///
+/// magic     "HHBC"
+/// version   u8
+/// opcodes   varint count, then per opcode: u8 length, mnemonic bytes, u8 arity
+/// program   varint count, then per instruction: varint opcode index and,
+///           when the opcode takes one, a zigzag varint argument
+///
+/// Opcodes are stored by name so a program only decodes against an instruction set that
+/// defines every one of them the same way.
+const MAGIC: &[u8] = b"HHBC";
+pub const VERSION: u8 = 1;
+
+pub fn encode(instructions: &[Instruction]) -> Vec<u8> {
+    let mut opcodes: Vec<Opcode> = Vec::new();
+    for instruction in instructions {
+        if !opcodes.contains(&instruction.op) {
+            opcodes.push(instruction.op);
+        }
+    }
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.push(VERSION);
+    write_varint(&mut bytes, opcodes.len() as u64);
+    for opcode in &opcodes {
+        bytes.push(opcode.mnemonic.len() as u8);
+        bytes.extend_from_slice(opcode.mnemonic.as_bytes());
+        bytes.push(opcode.arity as u8);
+    }
+
+    write_varint(&mut bytes, instructions.len() as u64);
+    for instruction in instructions {
+        let index = opcodes.iter().position(|op| *op == instruction.op).unwrap();
+        write_varint(&mut bytes, index as u64);
+        if instruction.op.arity > 0 {
+            write_varint(&mut bytes, zigzag(instruction.arg));
+        }
+    }
+    bytes
+}
+
+pub fn decode(
+    bytes: &[u8],
+    instruction_set: &InstructionSet,
+) -> Result<Vec<Instruction>, VirtualMachineError> {
+    let mut reader = Reader { bytes, offset: 0 };
+
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(reader.error(0, "not handheld bytecode".to_string()));
+    }
+    let version = reader.byte()?;
+    if version != VERSION {
+        return Err(reader.error(MAGIC.len(), format!("unsupported version {}", version)));
+    }
+
+    let count = reader.varint()?;
+    let mut opcodes: Vec<Opcode> = Vec::new();
+    for _ in 0..count {
+        let start = reader.offset;
+        let length = reader.byte()? as usize;
+        let mnemonic = std::str::from_utf8(reader.take(length)?)
+            .map_err(|_| reader.error(start + 1, "opcode name is not UTF-8".to_string()))?;
+        let arity = reader.byte()? as usize;
+        let definition = instruction_set
+            .get(mnemonic)
+            .ok_or(reader.error(start, format!("unknown opcode {}", mnemonic)))?;
+        if definition.opcode.arity != arity {
+            return Err(reader.error(
+                start,
+                format!(
+                    "opcode {} has arity {} but the instruction set expects {}",
+                    mnemonic, arity, definition.opcode.arity
+                ),
+            ));
+        }
+        opcodes.push(definition.opcode);
+    }
+
+    let count = reader.varint()?;
+    let mut instructions = Vec::new();
+    for _ in 0..count {
+        let start = reader.offset;
+        let index = reader.varint()?;
+        let op = *opcodes.get(index as usize).ok_or(reader.error(
+            start,
+            format!("opcode index {} is not in the opcode table", index),
+        ))?;
+        let arg = match op.arity {
+            0 => 0,
+            _ => unzigzag(reader.varint()?),
+        };
+        instructions.push(Instruction { op, arg });
+    }
+
+    if reader.offset != bytes.len() {
+        return Err(reader.error(
+            reader.offset,
+            format!("{} trailing bytes", bytes.len() - reader.offset),
+        ));
+    }
+    Ok(instructions)
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, offset: usize, message: String) -> VirtualMachineError {
+        VirtualMachineError::Custom(format!("byte {}: {}", offset, message))
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], VirtualMachineError> {
+        if self.offset + length > self.bytes.len() {
+            return Err(self.error(self.bytes.len(), "unexpected end of bytecode".to_string()));
+        }
+        let taken = &self.bytes[self.offset..self.offset + length];
+        self.offset += length;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, VirtualMachineError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64, VirtualMachineError> {
+        let start = self.offset;
+        let mut value: u64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift == 63 && byte > 1 {
+                return Err(self.error(start, "varint overflows 64 bits".to_string()));
+            }
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handheld::bytecode::{decode, encode};
+    use crate::handheld::{Instruction, InstructionSet, ACC, HALT, JMP};
+
+    #[test]
+    fn round_trip() {
+        let instructions = vec![
+            Instruction { op: ACC, arg: -99 },
+            Instruction { op: JMP, arg: 300 },
+            Instruction { op: HALT, arg: 0 },
+            Instruction {
+                op: ACC,
+                arg: i64::MIN,
+            },
+        ];
+        let bytes = encode(&instructions);
+        assert_eq!(&bytes[..5], b"HHBC\x01");
+        assert_eq!(
+            decode(&bytes, &InstructionSet::extended()),
+            Ok(instructions)
+        );
+    }
+
+    #[test]
+    fn corruption() {
+        let instructions = vec![Instruction { op: HALT, arg: 0 }];
+        let bytes = encode(&instructions);
+        assert_eq!(
+            decode(&bytes, &InstructionSet::boot_code())
+                .unwrap_err()
+                .to_string(),
+            "Error: byte 6: unknown opcode halt"
+        );
+
+        let mut bytes = encode(&[Instruction { op: ACC, arg: 1 }]);
+        // point the only instruction at a second opcode that doesn't exist
+        let last = bytes.len() - 2;
+        bytes[last] = 1;
+        assert_eq!(
+            decode(&bytes, &InstructionSet::boot_code())
+                .unwrap_err()
+                .to_string(),
+            "Error: byte 12: opcode index 1 is not in the opcode table"
+        );
+
+        // and then cut its argument off
+        bytes[last] = 0;
+        bytes.truncate(bytes.len() - 1);
+        assert_eq!(
+            decode(&bytes, &InstructionSet::boot_code())
+                .unwrap_err()
+                .to_string(),
+            "Error: byte 13: unexpected end of bytecode"
+        );
+    }
+}
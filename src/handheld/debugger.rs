@@ -0,0 +1,338 @@
+use crate::handheld::repair::flipped;
+use crate::handheld::{Instruction, Register, VirtualMachine, VirtualMachineError};
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+// how many instructions to show either side of the pc in a listing
+const LISTING_CONTEXT: usize = 3;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Command {
+    Step(usize),
+    Back(usize),
+    Continue,
+    Break(usize),
+    Delete(usize),
+    Watch,
+    Unwatch,
+    List,
+    Visited,
+    Registers,
+    Patch(usize, String),
+    Flip(usize),
+    Reset,
+    Help,
+    Quit,
+}
+
+impl Command {
+    pub fn from_line(line: &str) -> Result<Command, VirtualMachineError> {
+        let parts: Vec<&str> = line.split_ascii_whitespace().collect();
+        let address = |index: usize| {
+            parts
+                .get(index)
+                .and_then(|pc| pc.parse::<usize>().ok())
+                .ok_or(VirtualMachineError::Custom(format!(
+                    "{} needs an address",
+                    parts[0]
+                )))
+        };
+        match parts.first().copied() {
+            None | Some("s") | Some("step") => match parts.get(1) {
+                Some(count) => count
+                    .parse::<usize>()
+                    .map(Command::Step)
+                    .map_err(|_| VirtualMachineError::Custom("Invalid step count".to_string())),
+                None => Ok(Command::Step(1)),
+            },
+            Some("back") => match parts.get(1) {
+                Some(count) => count
+                    .parse::<usize>()
+                    .map(Command::Back)
+                    .map_err(|_| VirtualMachineError::Custom("Invalid step count".to_string())),
+                None => Ok(Command::Back(1)),
+            },
+            Some("c") | Some("continue") => Ok(Command::Continue),
+            Some("b") | Some("break") => Ok(Command::Break(address(1)?)),
+            Some("d") | Some("delete") => Ok(Command::Delete(address(1)?)),
+            Some("w") | Some("watch") => Ok(Command::Watch),
+            Some("unwatch") => Ok(Command::Unwatch),
+            Some("l") | Some("list") => Ok(Command::List),
+            Some("v") | Some("visited") => Ok(Command::Visited),
+            Some("r") | Some("registers") => Ok(Command::Registers),
+            Some("p") | Some("patch") => {
+                let pc = address(1)?;
+                if parts.len() < 3 {
+                    return Err(VirtualMachineError::Custom(
+                        "patch needs an instruction".to_string(),
+                    ));
+                }
+                Ok(Command::Patch(pc, parts[2..].join(" ")))
+            }
+            Some("f") | Some("flip") => Ok(Command::Flip(address(1)?)),
+            Some("reset") => Ok(Command::Reset),
+            Some("h") | Some("help") => Ok(Command::Help),
+            Some("q") | Some("quit") => Ok(Command::Quit),
+            Some(unknown) => Err(VirtualMachineError::Custom(format!(
+                "Unknown command: {}",
+                unknown
+            ))),
+        }
+    }
+}
+
+const HELP: &str = "step [n]        execute n instructions (default 1)
+back [n]        undo n instructions (default 1)
+continue        run until a breakpoint, watchpoint or the program stops
+break <pc>      stop before executing pc
+delete <pc>     remove the breakpoint at pc
+watch           stop whenever acc changes
+unwatch         stop watching acc
+list            show the instructions around pc
+visited         show every pc executed so far
+registers       show pc and registers
+patch <pc> <instruction>
+                replace the instruction at pc
+flip <pc>       swap a jmp for a nop or a nop for a jmp
+reset           restart the program keeping patches and breakpoints
+quit            leave the debugger
+";
+
+/// Drives a [VirtualMachine] one command at a time, as typed into a REPL.
+pub struct Debugger {
+    pub vm: VirtualMachine,
+    pub breakpoints: BTreeSet<usize>,
+    pub watch_acc: bool,
+    // set once the program has finished or failed so we don't keep stepping it
+    stopped: Option<VirtualMachineError>,
+}
+
+impl Debugger {
+    pub fn new(mut vm: VirtualMachine) -> Debugger {
+        if vm.undo_log.is_none() {
+            vm.undo_log = Some(Vec::new());
+        }
+        Debugger {
+            vm,
+            breakpoints: BTreeSet::new(),
+            watch_acc: false,
+            stopped: None,
+        }
+    }
+
+    /// Runs a command and returns what should be shown to the user.
+    pub fn execute(&mut self, command: &Command) -> Result<String, VirtualMachineError> {
+        match command {
+            Command::Step(count) => {
+                for _ in 0..*count {
+                    if let Some(reason) = self.step() {
+                        return Ok(reason);
+                    }
+                }
+                Ok(self.listing())
+            }
+            Command::Back(count) => {
+                for _ in 0..*count {
+                    self.vm.step_back()?;
+                    self.stopped = None;
+                }
+                Ok(self.listing())
+            }
+            Command::Continue => {
+                // always execute at least one instruction so we can leave a breakpoint
+                let mut first = true;
+                loop {
+                    if !first && self.breakpoints.contains(&self.vm.pc) {
+                        return Ok(format!(
+                            "breakpoint at pc {}\n{}",
+                            self.vm.pc,
+                            self.listing()
+                        ));
+                    }
+                    first = false;
+                    let acc = self.vm.acc();
+                    if let Some(reason) = self.step() {
+                        return Ok(reason);
+                    }
+                    if self.watch_acc && self.vm.acc() != acc {
+                        return Ok(format!(
+                            "acc changed from {} to {}\n{}",
+                            acc,
+                            self.vm.acc(),
+                            self.listing()
+                        ));
+                    }
+                }
+            }
+            Command::Break(pc) => {
+                self.check_address(*pc)?;
+                self.breakpoints.insert(*pc);
+                Ok(format!("breakpoint set at pc {}\n", pc))
+            }
+            Command::Delete(pc) => match self.breakpoints.remove(pc) {
+                true => Ok(format!("breakpoint removed from pc {}\n", pc)),
+                false => Err(VirtualMachineError::Custom(format!(
+                    "No breakpoint at pc {}",
+                    pc
+                ))),
+            },
+            Command::Watch => {
+                self.watch_acc = true;
+                Ok("watching acc\n".to_string())
+            }
+            Command::Unwatch => {
+                self.watch_acc = false;
+                Ok("no longer watching acc\n".to_string())
+            }
+            Command::List => Ok(self.listing()),
+            Command::Visited => {
+                let visited: BTreeSet<&usize> = self.vm.seen_pc.iter().collect();
+                let visited: Vec<String> = visited.iter().map(|pc| pc.to_string()).collect();
+                Ok(format!("visited: {}\n", visited.join(" ")))
+            }
+            Command::Registers => Ok(format!(
+                "pc: {} acc: {} b: {}\n",
+                self.vm.pc,
+                self.vm.cpu.registers[Register::A],
+                self.vm.cpu.registers[Register::B]
+            )),
+            Command::Patch(pc, line) => {
+                self.check_address(*pc)?;
+                let instruction = self.vm.instruction_set().parse(line)?;
+                Ok(self.patch(*pc, instruction))
+            }
+            Command::Flip(pc) => {
+                self.check_address(*pc)?;
+                let instruction = &self.vm.instructions[*pc];
+                let instruction = flipped(instruction).ok_or(VirtualMachineError::Custom(
+                    format!("Can only flip jmp and nop, not {}", instruction.op),
+                ))?;
+                Ok(self.patch(*pc, instruction))
+            }
+            Command::Reset => {
+                self.vm.reset();
+                self.stopped = None;
+                Ok(self.listing())
+            }
+            Command::Help => Ok(HELP.to_string()),
+            Command::Quit => Ok(String::new()),
+        }
+    }
+
+    // steps once, returning why the program stopped if it did
+    fn step(&mut self) -> Option<String> {
+        if let Some(reason) = &self.stopped {
+            return Some(format!("program already stopped: {}\n", reason));
+        }
+        match self.vm.step() {
+            Ok(()) => None,
+            Err(err) => {
+                let reason = format!("program stopped: {} acc = {}\n", err, self.vm.acc());
+                self.stopped = Some(err);
+                Some(reason)
+            }
+        }
+    }
+
+    fn patch(&mut self, pc: usize, instruction: Instruction) -> String {
+        let original = std::mem::replace(&mut self.vm.instructions[pc], instruction);
+        format!(
+            "patched pc {}: {} -> {}\n",
+            pc, original, self.vm.instructions[pc]
+        )
+    }
+
+    fn check_address(&self, pc: usize) -> Result<(), VirtualMachineError> {
+        if pc >= self.vm.instructions.len() {
+            return Err(VirtualMachineError::Custom(format!(
+                "pc {} is outside the program of {} instructions",
+                pc,
+                self.vm.instructions.len()
+            )));
+        }
+        Ok(())
+    }
+
+    /// The instructions around the current pc, marking the pc, breakpoints and visited pcs.
+    pub fn listing(&self) -> String {
+        let pc = self.vm.pc;
+        let start = pc.saturating_sub(LISTING_CONTEXT);
+        let end = (pc + LISTING_CONTEXT + 1).min(self.vm.instructions.len());
+        let mut listing = String::new();
+        for address in start..end {
+            let marker = if address == pc { "=>" } else { "  " };
+            let breakpoint = if self.breakpoints.contains(&address) {
+                "*"
+            } else {
+                " "
+            };
+            let visited = if self.vm.seen_pc.contains(&address) {
+                "v"
+            } else {
+                " "
+            };
+            writeln!(
+                listing,
+                "{}{}{} {:>4}: {}",
+                marker, breakpoint, visited, address, self.vm.instructions[address]
+            )
+            .unwrap();
+        }
+        if pc >= self.vm.instructions.len() {
+            writeln!(listing, "=>   {:>4}: <end of program>", pc).unwrap();
+        }
+        listing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handheld::debugger::{Command, Debugger};
+    use crate::handheld::VirtualMachine;
+
+    const SAMPLE: &str = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+
+    fn run(debugger: &mut Debugger, line: &str) -> String {
+        let command = Command::from_line(line).unwrap();
+        debugger.execute(&command).unwrap()
+    }
+
+    #[test]
+    fn breakpoints_and_watchpoints() {
+        let mut debugger = Debugger::new(VirtualMachine::new(SAMPLE).unwrap());
+        run(&mut debugger, "break 6");
+        assert!(run(&mut debugger, "continue").starts_with("breakpoint at pc 6"));
+        assert_eq!(debugger.vm.acc(), 1);
+        run(&mut debugger, "watch");
+        assert!(run(&mut debugger, "c").starts_with("acc changed from 1 to 2"));
+        assert_eq!(run(&mut debugger, "visited"), "visited: 0 1 2 6\n");
+        assert!(run(&mut debugger, "step 4").starts_with("program stopped: Infinite"));
+    }
+
+    #[test]
+    fn flip_and_resume() {
+        let mut debugger = Debugger::new(VirtualMachine::new(SAMPLE).unwrap());
+        assert_eq!(
+            run(&mut debugger, "flip 7"),
+            "patched pc 7: jmp -4 -> nop -4\n"
+        );
+        assert!(Command::from_line("flip 9")
+            .and_then(|command| debugger.execute(&command))
+            .is_err());
+        assert!(run(&mut debugger, "continue").starts_with("program stopped: Finished. acc = 8"));
+        run(&mut debugger, "back 2");
+        assert_eq!((debugger.vm.pc, debugger.vm.acc()), (7, 2));
+        run(&mut debugger, "reset");
+        run(&mut debugger, "patch 8 acc +10");
+        run(&mut debugger, "continue");
+        assert_eq!(debugger.vm.acc(), 12);
+    }
+}
@@ -0,0 +1,208 @@
+use crate::handheld::{Instruction, VirtualMachine, VirtualMachineError, HALT, JMP, NOP};
+use std::collections::VecDeque;
+
+/// The single jmp/nop swap that lets a looping program run to completion.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Repair {
+    pub index: usize,
+    pub original: Instruction,
+    pub patched: Instruction,
+    // the accumulator once the patched program finishes
+    pub acc: i64,
+}
+
+pub fn flipped(instruction: &Instruction) -> Option<Instruction> {
+    let op = match instruction.op {
+        JMP => NOP,
+        NOP => JMP,
+        _ => return None,
+    };
+    Some(Instruction {
+        op,
+        arg: instruction.arg,
+    })
+}
+
+// where control goes after the instruction at pc; None when it halts.
+// anything out of range, including the end of the program, is returned as is
+fn successor(pc: usize, instruction: &Instruction) -> Result<Option<i64>, VirtualMachineError> {
+    match instruction.op {
+        JMP => (pc as i64)
+            .checked_add(instruction.arg)
+            .map(Some)
+            .ok_or(VirtualMachineError::Overflow { pc }),
+        HALT => Ok(None),
+        op if op.jumps => Err(VirtualMachineError::Custom(format!(
+            "Cannot repair programs using the conditional jump {}",
+            op
+        ))),
+        _ => Ok(Some(pc as i64 + 1)),
+    }
+}
+
+/// Finds the repair in time linear to the program length.
+///
+/// Every pc that reaches the end of the program is found by walking the control flow
+/// graph backwards from the end. The original execution path is then followed and the
+/// first jmp or nop whose flipped successor is one of those pcs is the repair. The flip
+/// can't lead back to itself: if it did, the original path would terminate too.
+pub fn repair(vm: &VirtualMachine) -> Result<Repair, VirtualMachineError> {
+    let instructions = &vm.instructions;
+    let end = instructions.len();
+
+    // index end is the virtual node for "finished"
+    let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); end + 1];
+    let mut terminates = vec![false; end + 1];
+    for (pc, instruction) in instructions.iter().enumerate() {
+        match successor(pc, instruction)? {
+            Some(next) if next >= 0 && next as usize <= end => predecessors[next as usize].push(pc),
+            // halting is a normal way to finish
+            None => terminates[pc] = true,
+            // jumping anywhere else is a crash
+            Some(_) => {}
+        }
+    }
+
+    let mut queue: VecDeque<usize> = VecDeque::new();
+    terminates[end] = true;
+    queue.extend((0..=end).filter(|&pc| terminates[pc]));
+    while let Some(pc) = queue.pop_front() {
+        for &previous in &predecessors[pc] {
+            if !terminates[previous] {
+                terminates[previous] = true;
+                queue.push_back(previous);
+            }
+        }
+    }
+
+    if terminates[0] {
+        return Err(VirtualMachineError::Custom(
+            "Program already terminates".to_string(),
+        ));
+    }
+
+    // follow the original path; it must loop or crash since it doesn't terminate
+    let mut visited = vec![false; end];
+    let mut pc = 0i64;
+    while pc >= 0 && (pc as usize) < end && !visited[pc as usize] {
+        let current = pc as usize;
+        visited[current] = true;
+        let instruction = &instructions[current];
+        if let Some(patched) = flipped(instruction) {
+            // the flipped successor is always Some since it's a jmp or nop
+            let next = successor(current, &patched)?.unwrap();
+            if next >= 0 && next as usize <= end && terminates[next as usize] {
+                return finish(vm, current, patched);
+            }
+        }
+        pc = match successor(current, instruction)? {
+            Some(next) => next,
+            None => break,
+        };
+    }
+
+    Err(VirtualMachineError::Custom(
+        "No single jmp/nop flip makes the program terminate".to_string(),
+    ))
+}
+
+/// Finds the repair by running the program and trying every jmp or nop it reaches flipped,
+/// rewinding to a snapshot whenever the flip doesn't help. This is quadratic in the worst
+/// case so it's mostly useful as a cross-check of [repair].
+pub fn repair_by_search(vm: &VirtualMachine) -> Result<Repair, VirtualMachineError> {
+    let mut vm =
+        VirtualMachine::from_instructions(vm.instructions.clone(), vm.instruction_set().clone());
+    loop {
+        let index = vm.pc;
+        if let Some(patched) = vm.instructions.get(index).and_then(flipped) {
+            let snapshot = vm.snapshot();
+            let original = std::mem::replace(&mut vm.instructions[index], patched.clone());
+            if vm.execute().is_ok() {
+                return Ok(Repair {
+                    index,
+                    original,
+                    patched,
+                    acc: vm.acc(),
+                });
+            }
+            vm.instructions[index] = original;
+            vm.restore(&snapshot);
+        }
+
+        match vm.step() {
+            Ok(()) => {}
+            Err(VirtualMachineError::Finished) => {
+                return Err(VirtualMachineError::Custom(
+                    "Program already terminates".to_string(),
+                ))
+            }
+            Err(_) => {
+                return Err(VirtualMachineError::Custom(
+                    "No single jmp/nop flip makes the program terminate".to_string(),
+                ))
+            }
+        }
+    }
+}
+
+fn finish(
+    vm: &VirtualMachine,
+    index: usize,
+    patched: Instruction,
+) -> Result<Repair, VirtualMachineError> {
+    let mut instructions = vm.instructions.clone();
+    let original = std::mem::replace(&mut instructions[index], patched.clone());
+    let mut repaired =
+        VirtualMachine::from_instructions(instructions, vm.instruction_set().clone());
+    repaired.execute()?;
+    Ok(Repair {
+        index,
+        original,
+        patched,
+        acc: repaired.acc(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handheld::repair::{repair, repair_by_search, Repair};
+    use crate::handheld::{Instruction, VirtualMachine, VirtualMachineError, JMP, NOP};
+
+    #[test]
+    fn repairs_sample() {
+        let sample = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+        let vm = VirtualMachine::new(sample).unwrap();
+        assert_eq!(repair(&vm), repair_by_search(&vm));
+        assert_eq!(
+            repair(&vm),
+            Ok(Repair {
+                index: 7,
+                original: Instruction { op: JMP, arg: -4 },
+                patched: Instruction { op: NOP, arg: -4 },
+                acc: 8
+            })
+        );
+    }
+
+    #[test]
+    fn nothing_to_repair() {
+        let vm = VirtualMachine::new("acc +1\nnop +0").unwrap();
+        assert!(repair(&vm).is_err());
+        let vm = VirtualMachine::new("jmp +0\njmp -1").unwrap();
+        assert!(repair(&vm).is_err());
+        assert!(repair_by_search(&vm).is_err());
+        // the first flip along the original path wins
+        let vm = VirtualMachine::new("nop +2\njmp +0\nacc +1").unwrap();
+        assert_eq!(repair(&vm).unwrap().index, 0);
+        let vm = VirtualMachine::new("nop +0\njmp +9223372036854775807").unwrap();
+        assert_eq!(repair(&vm), Err(VirtualMachineError::Overflow { pc: 1 }));
+    }
+}
@@ -0,0 +1,205 @@
+use crate::handheld::Instruction;
+use std::collections::BTreeMap;
+use std::io;
+use std::io::Write;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct TraceEntry {
+    pub step: usize,
+    pub pc: usize,
+    pub instruction: Instruction,
+    pub acc_before: i64,
+    pub acc_after: i64,
+    // signed since a bad jump can point before the start of the program; None once halted
+    pub next_pc: Option<i64>,
+}
+
+/// Every instruction a [VirtualMachine](crate::handheld::VirtualMachine) executed, in order.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Trace {
+    pub entries: Vec<TraceEntry>,
+    // whether the run was stopped for revisiting an instruction
+    pub looped: bool,
+}
+
+impl Trace {
+    pub fn record(
+        &mut self,
+        pc: usize,
+        instruction: Instruction,
+        acc_before: i64,
+        acc_after: i64,
+        next_pc: Option<i64>,
+    ) {
+        self.entries.push(TraceEntry {
+            step: self.entries.len(),
+            pc,
+            instruction,
+            acc_before,
+            acc_after,
+            next_pc,
+        });
+    }
+
+    /// How many times each pc was executed, along with its instruction.
+    pub fn hit_counts(&self) -> BTreeMap<usize, (&Instruction, usize)> {
+        let mut hits: BTreeMap<usize, (&Instruction, usize)> = BTreeMap::new();
+        for entry in &self.entries {
+            hits.entry(entry.pc).or_insert((&entry.instruction, 0)).1 += 1;
+        }
+        hits
+    }
+
+    /// The cycle of pcs that closed when the last instruction went back to an
+    /// already executed pc, starting from that pc. Only a run stopped by loop
+    /// detection has one.
+    pub fn cycle(&self) -> Option<Vec<usize>> {
+        if !self.looped {
+            return None;
+        }
+        let target = self.entries.last()?.next_pc?;
+        // the last entry itself matches when an instruction jumps to itself
+        let start = self
+            .entries
+            .iter()
+            .rposition(|entry| entry.pc as i64 == target)?;
+        Some(self.entries[start..].iter().map(|entry| entry.pc).collect())
+    }
+
+    pub fn write_text<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(
+            writer,
+            "step\tpc\tinstruction\tacc before\tacc after\tnext pc"
+        )?;
+        for entry in &self.entries {
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                entry.step,
+                entry.pc,
+                entry.instruction,
+                entry.acc_before,
+                entry.acc_after,
+                entry
+                    .next_pc
+                    .map_or("halt".to_string(), |next_pc| next_pc.to_string())
+            )?;
+        }
+
+        writeln!(writer, "\nhits:")?;
+        for (pc, (instruction, count)) in self.hit_counts() {
+            writeln!(writer, "{}\t{}\t{}", pc, instruction, count)?;
+        }
+        if let Some(cycle) = self.cycle() {
+            let pcs: Vec<String> = cycle.iter().map(|pc| pc.to_string()).collect();
+            writeln!(writer, "\nloop: {} -> {}", pcs.join(" -> "), cycle[0])?;
+        }
+        Ok(())
+    }
+
+    /// One JSON object per step followed by a final summary object.
+    pub fn write_json_lines<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for entry in &self.entries {
+            writeln!(
+                writer,
+                "{{\"step\":{},\"pc\":{},\"instruction\":\"{}\",\"acc_before\":{},\"acc_after\":{},\"next_pc\":{}}}",
+                entry.step,
+                entry.pc,
+                entry.instruction,
+                entry.acc_before,
+                entry.acc_after,
+                entry
+                    .next_pc
+                    .map_or("null".to_string(), |next_pc| next_pc.to_string())
+            )?;
+        }
+
+        let hits: Vec<String> = self
+            .hit_counts()
+            .into_iter()
+            .map(|(pc, (instruction, count))| {
+                format!(
+                    "{{\"pc\":{},\"instruction\":\"{}\",\"count\":{}}}",
+                    pc, instruction, count
+                )
+            })
+            .collect();
+        let cycle = match self.cycle() {
+            Some(cycle) => {
+                let pcs: Vec<String> = cycle.iter().map(|pc| pc.to_string()).collect();
+                format!("[{}]", pcs.join(","))
+            }
+            None => "null".to_string(),
+        };
+        writeln!(
+            writer,
+            "{{\"summary\":{{\"steps\":{},\"hits\":[{}],\"loop\":{}}}}}",
+            self.entries.len(),
+            hits.join(","),
+            cycle
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handheld::trace::Trace;
+    use crate::handheld::{InstructionSet, VirtualMachine};
+
+    const SAMPLE: &str = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6";
+
+    fn traced_sample() -> Trace {
+        let mut vm = VirtualMachine::new(SAMPLE).unwrap();
+        vm.trace = Some(Trace::default());
+        assert!(vm.execute().is_err());
+        vm.trace.unwrap()
+    }
+
+    #[test]
+    fn loop_detection() {
+        let trace = traced_sample();
+        assert_eq!(trace.entries.len(), 7);
+        assert_eq!(trace.cycle(), Some(vec![1, 2, 6, 7, 3, 4]));
+        assert_eq!(trace.hit_counts()[&1].1, 1);
+    }
+
+    #[test]
+    fn halting_is_not_a_loop() {
+        let mut vm =
+            VirtualMachine::with_instruction_set("acc +1\nhalt", InstructionSet::extended())
+                .unwrap();
+        vm.trace = Some(Trace::default());
+        assert!(vm.execute().is_ok());
+        let trace = vm.trace.unwrap();
+        assert_eq!(trace.entries[1].next_pc, None);
+        assert_eq!(trace.cycle(), None);
+
+        let mut output: Vec<u8> = Vec::new();
+        trace.write_text(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("1\thalt\t1\t1\thalt\n"));
+        assert!(!output.contains("loop:"));
+    }
+
+    #[test]
+    fn json_lines() {
+        let mut output: Vec<u8> = Vec::new();
+        traced_sample().write_json_lines(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 8);
+        assert_eq!(
+            lines[2],
+            "{\"step\":2,\"pc\":2,\"instruction\":\"jmp +4\",\"acc_before\":1,\"acc_after\":1,\"next_pc\":6}"
+        );
+        assert!(lines[7].ends_with("\"loop\":[1,2,6,7,3,4]}}"));
+    }
+}
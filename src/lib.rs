@@ -1,3 +1,7 @@
+#![feature(str_split_once)]
+
+pub mod handheld;
+
 use std::error::Error;
 use std::fmt;
 